@@ -1,139 +1,87 @@
-use std::{collections::VecDeque, fs, ops::Neg};
-use euclid::{Vector2D, UnknownUnit, vec2};
-use itertools::Itertools;
+use std::{fs::File, io::BufWriter};
 
-use crate::int::{Computer, ComputerState};
-mod int;
+use intcode::{
+    arcade::{Arcade, Controller, FollowBall, FrameLog, PredictLanding},
+    program::Program,
+    replay::Recorder,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Default)]
-enum TileType {
-    #[default] Empty,
-    Wall,
-    Block,
-    HPaddle,
-    Ball,
+// the program with free play on, address 0 set to 2 coins the way
+// `Arcade::with_coins` does it, so sessions replay against what ran
+fn with_coins(prog: &Program) -> Program {
+    let mut code = prog.code.clone();
+    code[0] = 2;
+    Program { code }
 }
 
-impl Into<char> for TileType {
-    fn into(self) -> char {
-        match self {
-            Self::Empty => ' ',
-            Self::Wall => 'W',
-            Self::Block => 'B',
-            Self::HPaddle => '_',
-            Self::Ball => 'O',
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Default)]
-struct Tile {
-    pos: Vector2D<i64, UnknownUnit>,
-    content: TileType,
+// plays a whole game with `PredictLanding`, recording its I/O
+fn record_game(prog: &Program) -> (Arcade, Recorder) {
+    let coined = with_coins(prog);
+    let mut recorder = Recorder::new(&coined);
+    let mut arcade = Arcade::new(&coined);
+    arcade.play_traced(&mut PredictLanding, &mut recorder).unwrap();
+    (arcade, recorder)
 }
 
 fn main() {
-    // let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = std::env::args().collect();
+    let follow = args.iter().any(|a| a == "--follow");
+    let show = args.iter().any(|a| a == "--show");
+    let log_path = args.iter().position(|a| a == "--log").and_then(|i| args.get(i + 1));
+    // `--record <session.txt>` saves the predicting bot's game for replaying
+    let record_path = args.iter().position(|a| a == "--record").and_then(|i| args.get(i + 1));
+
     let prog_path = "aoc13/prog.txt";
-    // println!("Prog at {}, input at {}", prog_path, input_path);
+    let prog = Program::load(prog_path).expect("Something went wrong reading the program");
 
-    let prog_str = fs::read_to_string(prog_path).expect("Something went wrong reading the file");
+    // without coins the game only draws the board
+    let mut demo = Arcade::new(&prog);
+    demo.play(&mut ()).unwrap();
 
-    let orig_prog: Vec<i64> = prog_str
-        .replace("\n", "")
-        .split(',')
-        .map(|line| line.parse::<i64>().unwrap())
-        .collect();
+    if let Some(path) = record_path {
+        let (arcade, recorder) = record_game(&prog);
+        recorder.session.save(path).expect("Something went wrong saving the session");
+        println!("recorded score {} frames {}", arcade.score, arcade.frame);
+        return;
+    }
 
-    let mut comp_mem = orig_prog.clone();
-    comp_mem.resize(8000, 0);
-    // adjust arcade money in memory
-    comp_mem[0] = 2;
-    let mut comp = Computer {
-        eip: 0,
-        esp: 0,
-        mem: comp_mem,
-        ins: VecDeque::from([0]),
-        outs: VecDeque::new(),
-    };
+    let mut ctrl: Box<dyn Controller> = if follow { Box::new(FollowBall) } else { Box::new(PredictLanding) };
+    let mut log = log_path.map(|path| FrameLog::new(BufWriter::new(File::create(path).expect("Can't create frame log"))));
+    let mut arcade = Arcade::with_coins(&prog, 2);
+    arcade
+        .play_observed(&mut ctrl, |a| {
+            if show {
+                // clear the terminal and draw over the previous frame
+                print!("\x1b[2J\x1b[H{}", a);
+            }
+            if let Some(log) = &mut log {
+                log.record(a);
+            }
+        })
+        .unwrap();
+    if let Some(log) = log {
+        log.finish().expect("Can't write frame log");
+    }
 
-    let map_width = 35i64;
-    let map_heigth: i64 = 23i64;
-    let mut tiles: Vec<Tile> = vec![Tile::default(); (map_width * map_heigth) as usize];
-    let mut block_counter = 0;
-    let mut ball: Vector2D<i64, UnknownUnit> = vec2(0, 0);
-    let mut paddle: Vector2D<i64, UnknownUnit> = vec2(0, 0);
-    let mut score = 0;
-    let output_ring_max = 12;
-    let mut output_ring: VecDeque<Tile> = VecDeque::with_capacity(output_ring_max);
-    let debug = |tiles: &Vec<Tile>, output_ring: &VecDeque<Tile>| {
-        let mut tiles_clone = tiles.clone();
-        output_ring.iter().for_each(|t| {
-            let tile = &mut tiles_clone[(t.pos.y * map_width + t.pos.x) as usize];
-            tile.content = match t.content {
-                TileType::Ball => t.content,
-                TileType::Empty => TileType::Wall,
-                _ => tile.content
-            };
-            // println!("[{}, {}] {:?}", t.pos.x, t.pos.y, t.content)
-        });
-        tiles_clone.chunks(map_width as usize).into_iter().enumerate().for_each(|(i, ch)| {
-            let chars: String = ch.iter().map(|t| -> char { t.content.into() }).collect();
-            println!("{: >2} {}", i, chars);
-        });
-    };
+    println!("block tiles {} score {} frames {}", demo.blocks(), arcade.score, arcade.frame);
+    print!("{}", arcade.screen);
+}
 
-    let mut state = ComputerState::Interrupt;
-    while ComputerState::Interrupt == state && comp.outs.len() < 3 {
-        state = comp.run().unwrap();
-        if comp.outs.len() >= 3 {
-            let (x, y, t) = comp.outs.drain(..3).collect_tuple().unwrap();
-            if x == -1 && y == 0 {
-                score = t;
-                continue;
-            }
+#[cfg(test)]
+mod tests {
+    use intcode::replay::Session;
 
-            let tile_content: TileType = match t {
-                0 => TileType::Empty,
-                1 => TileType::Wall,
-                2 => TileType::Block,
-                3 => TileType::HPaddle,
-                4 => TileType::Ball,
-                _ => panic!("Unknown tile")
-            };
-            let tile_index = (y * map_width + x) as usize;
-            tiles[tile_index] = Tile {
-                pos: vec2(x, y),
-                content: tile_content,
-            };
-            if output_ring.len() >= output_ring_max {
-                output_ring.pop_front();
-            }
-            output_ring.push_back(tiles[tile_index]);
+    use super::*;
 
-            match tile_content {
-                TileType::Block => block_counter += 1,
-                TileType::Ball => {
-                    ball = vec2(x, y);
-                }
-                TileType::HPaddle => {
-                    paddle = vec2(x, y);
-                }
-                _ => ()
-            }
+    #[test]
+    fn test_session_replays() {
+        let prog: Program = include_str!("../prog.txt").parse().unwrap();
+        let session: Session = include_str!("../session.txt").parse().unwrap();
+        assert_eq!(session.replay(&with_coins(&prog)), Ok(()));
 
-            if paddle != vec2(0, 0) && tile_content == TileType::Ball {
-                let dir: i64 = (ball.x - paddle.x).signum();
-                if comp.ins.is_empty() {
-                    comp.ins.push_back(dir);
-                } else {
-                    let front = comp.ins.front_mut().unwrap();
-                    *front = dir;
-                }
-            }
-        }
+        // re-record with `--record aoc13/session.txt` when the bot changes
+        let (arcade, recorder) = record_game(&prog);
+        assert_eq!((arcade.score, arcade.blocks()), (12952, 0));
+        assert_eq!(recorder.session, session);
     }
-
-    println!("block tiles {} score {} state {:?}", block_counter, score, state);
-    debug(&tiles, &output_ring);
 }
@@ -1,9 +1,8 @@
 use core::panic;
-use std::{collections::VecDeque, fs, ops::Range};
+use std::{collections::VecDeque, ops::Range};
 use euclid::{Vector2D, UnknownUnit, vec2};
 
-use crate::int::{Computer, ComputerState};
-mod int;
+use intcode::{int::{Computer, ComputerState}, program::Program};
 
 type Vec2i = Vector2D<i32, UnknownUnit>;
 
@@ -63,24 +62,18 @@ impl Map {
     }
 }
 
-fn part_one(orig_prog: &Vec<i64>) {
+fn part_one(prog: &Program) {
     let mut map = Map {
         tsize: vec2(50, 50),
         tiles: vec![Tiles::None; 50 * 50],
     };
 
     let mut map_pos: Vec2i = vec2(0, 0);
-    let mut state = ComputerState::Interrupt;
+    let mut state = ComputerState::WriteInt;
     let mut tile_counter = 0;
     while map_pos.y < map.tsize.y
     {
-        let mut comp = Computer {
-            eip: 0,
-            esp: 0,
-            mem: orig_prog.clone(),
-            ins: VecDeque::from([map_pos.x as i64, map_pos.y as i64]),
-            outs: VecDeque::new(),
-        };
+        let mut comp = Computer::new(prog).with_input([map_pos.x as i64, map_pos.y as i64]);
 
         state = comp.run().unwrap();
         if let Some(out) = comp.outs.pop_front() {
@@ -107,15 +100,9 @@ fn part_one(orig_prog: &Vec<i64>) {
     println!("Beam tiles {}", beam_tiles);
 }
 
-fn part_two(orig_prog: &Vec<i64>, rect_size: i32) {
-    fn compute_beam(orig_prog: &Vec<i64>, pos: Vec2i) -> Option<i64> {
-        let mut comp = Computer {
-            eip: 0,
-            esp: 0,
-            mem: orig_prog.clone(),
-            ins: VecDeque::from([pos.x as i64, pos.y as i64]),
-            outs: VecDeque::new(),
-        };
+fn part_two(prog: &Program, rect_size: i32) {
+    fn compute_beam(prog: &Program, pos: Vec2i) -> Option<i64> {
+        let mut comp = Computer::new(prog).with_input([pos.x as i64, pos.y as i64]);
 
         comp.run().unwrap();
         comp.outs.pop_front()
@@ -127,7 +114,7 @@ fn part_two(orig_prog: &Vec<i64>, rect_size: i32) {
         let mut maybe_range_start = None;
         let mut range_length = 1;
         let range = loop {
-            let out = compute_beam(orig_prog, map_pos).unwrap();
+            let out = compute_beam(prog, map_pos).unwrap();
             let tile = match out {
                 0 => Tiles::None,
                 1 => Tiles::Beam,
@@ -171,15 +158,8 @@ fn part_two(orig_prog: &Vec<i64>, rect_size: i32) {
 
 fn main() {
     let prog_path = "aoc19/prog.txt";
-    let prog_str = fs::read_to_string(prog_path).expect("Something went wrong reading the file");
-
-    let mut orig_prog: Vec<i64> = prog_str
-        .replace("\n", "")
-        .split(',')
-        .map(|line| line.parse::<i64>().unwrap())
-        .collect();
-    orig_prog.resize(8000, 0);
+    let prog = Program::load(prog_path).expect("Something went wrong reading the program");
 
-    // part_one(&orig_prog);
-    part_two(&orig_prog, 100)
+    // part_one(&prog);
+    part_two(&prog, 100)
 }
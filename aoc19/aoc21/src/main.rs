@@ -1,7 +1,4 @@
-use std::{collections::VecDeque, fs};
-
-use crate::int::{Computer, ComputerState};
-mod int;
+use intcode::{ascii::AsciiComputer, int::Computer, program::Program};
 
 enum SpringInstr {
     And(char, char),
@@ -23,24 +20,11 @@ impl SpringInstr {
     }
 }
 
-fn part_one(orig_prog: &Vec<i64>) {
-    let mut comp = Computer {
-        eip: 0,
-        esp: 0,
-        mem: orig_prog.clone(),
-        ins: VecDeque::from([]),
-        outs: VecDeque::new(),
-    };
+fn part_one(prog: &Program) {
+    let mut ascii = AsciiComputer::new(Computer::new(prog));
 
     // process until new line
-    while let Some(_) = comp.run() {
-        if let Some(out) = comp.outs.pop_front() {
-            print!("{}", char::from(out as u8));
-            if out == b'\n'.into() {
-                break;
-            }
-        }
-    }
+    println!("{}", ascii.read_line().unwrap().unwrap());
 
     // A: any hole in first 3
     // B: last is path
@@ -70,42 +54,18 @@ fn part_one(orig_prog: &Vec<i64>) {
         And('T', 'J'),
         Run,
     ];
-    comp.ins.extend(spring_prog_two
-        .map(|i| {
-            let mut s = i.to_ascii();
-            s.push_str("\n");
-            s
-        })
-        .join("")
-        .bytes()
-        .map(|b| b as i64));
-    let mut state = ComputerState::Interrupt;
-    while state != ComputerState::Halted
-    {
-        state = comp.run().unwrap();
-        if let Some(out) = comp.outs.pop_front() {
-            if out < u8::MAX as i64 {
-                print!("{}", char::from(out as u8));
-            } else {
-                println!("Success {}", out);
-            }
-        } else {
-            // println!("No output");
-            break;
-        }
+    for instr in spring_prog_two {
+        ascii.send_line(&instr.to_ascii());
+    }
+    print!("{}", ascii.read_until_prompt().unwrap());
+    for out in ascii.values.iter() {
+        println!("Success {}", out);
     }
 }
 
 fn main() {
     let prog_path = "aoc21/prog.txt";
-    let prog_str = fs::read_to_string(prog_path).expect("Something went wrong reading the file");
-
-    let mut orig_prog: Vec<i64> = prog_str
-        .replace("\n", "")
-        .split(',')
-        .map(|line| line.parse::<i64>().unwrap())
-        .collect();
-    orig_prog.resize(8000, 0);
+    let prog = Program::load(prog_path).expect("Something went wrong reading the program");
 
-    part_one(&orig_prog);
+    part_one(&prog);
 }
@@ -1,6 +1,8 @@
+// String-formatting decoder the interpreter used before integer decoding,
+// kept as the baseline for the decode benchmark and as a second opinion
+// for the fuzz tests.
 use std::{collections::VecDeque, fmt::Write};
 
-
 #[allow(dead_code)]
 #[derive(Debug, PartialEq)]
 enum OpCode {
@@ -52,7 +54,7 @@ fn parse_opcode(ins: &str, op: &mut Operation) {
         b"07" => OpCode::CmpLess,
         b"08" => OpCode::CmpEquals,
         b"09" => OpCode::SpAdd,
-        b"99" | _ => OpCode::Halt,
+        _ => OpCode::Halt,
     };
     let types: &[OpParamType] = match op.code {
         OpCode::Add => &[PT::Read, PT::Read, PT::Write],
@@ -110,6 +112,9 @@ fn parse_params(cmp: &Computer, op: &mut Operation) {
 
 #[derive(Clone)]
 pub struct Computer {
+    pub steps: u64,
+    // `run` gives up with `None` once `steps` gets here
+    pub max_steps: u64,
     pub eip: usize,
     pub esp: usize,
     pub mem: Vec<i64>,
@@ -136,17 +141,14 @@ impl Computer {
         let mut op_str: String = String::new();
 
         while let Some(eix) = self.mem.get(self.eip).cloned() {
+            if self.steps == self.max_steps {
+                return None;
+            }
             op_str.clear();
             write!(op_str, "{:0>5}", eix).unwrap();
             parse_opcode(&op_str, &mut op);
             parse_params(self, &mut op);
-            // let raw_slice = &cmp.mem[self.eip + 1..self.eip + op.params.len() + 1];
-            // println!("[{}] {:#?}", self.eip, op.code);
-            // println!("  esp: {}", self.esp);
-            // println!("  ptypes: {:?}", op.param_types);
-            // println!("  pmodes: {:?}", op.param_modes);
-            // println!("  rparam: {:?}", raw_slice);
-            // println!("  params: {:?}", op.params);
+            self.steps += 1;
 
             match op.code {
                 OpCode::Add | OpCode::Mul => {
@@ -189,7 +191,6 @@ impl Computer {
                 OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
                     match op.params[..] {
                         [eax, ebx] => {
-                            // println!("[{}] {}: {} > 0 => [{}]", self.eip, eix, eax, ebx);
                             let flag = match op.code {
                                 OpCode::JumpIfTrue => eax > 0,
                                 OpCode::JumpIfFalse => eax == 0,
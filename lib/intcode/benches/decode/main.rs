@@ -0,0 +1,47 @@
+use std::{collections::VecDeque, hint::black_box, time::Instant};
+
+use intcode::{int::{Computer, ComputerState}, program::Program};
+mod legacy;
+
+const BOOST_PROG: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../../aoc19/aoc9/prog.txt"));
+const RUNS: u32 = 20;
+
+fn main() {
+    let prog: Program = BOOST_PROG.parse().unwrap();
+    // the legacy interpreter can't grow its memory
+    let mut orig_prog = prog.code.clone();
+    orig_prog.resize(8000, 0);
+
+    // BOOST in sensor boost mode (input 2) runs a few hundred thousand
+    // instructions. Runs alternate between decoders and the fastest of
+    // each counts, so a busy machine doesn't skew the comparison.
+    let mut steps = 0;
+    let (mut legacy_secs, mut integer_secs) = (f64::MAX, f64::MAX);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let mut comp = legacy::Computer {
+            steps: 0,
+            max_steps: u64::MAX,
+            eip: 0,
+            esp: 0,
+            mem: orig_prog.clone(),
+            ins: VecDeque::from([2]),
+            outs: VecDeque::new(),
+        };
+        while let Some(legacy::ComputerState::WriteInt) = comp.run() {}
+        legacy_secs = legacy_secs.min(start.elapsed().as_secs_f64());
+        steps = comp.steps;
+        black_box(comp.outs);
+
+        let start = Instant::now();
+        let mut comp = Computer::new(&prog).with_input([2]);
+        while let Ok(ComputerState::WriteInt) = comp.run() {}
+        integer_secs = integer_secs.min(start.elapsed().as_secs_f64());
+        black_box(comp.outs);
+    }
+
+    println!("BOOST best of {} ({} instructions)", RUNS, steps);
+    println!("string decode  {:>14.0} instr/s", steps as f64 / legacy_secs);
+    println!("integer decode {:>14.0} instr/s", steps as f64 / integer_secs);
+    println!("speedup {:.2}x", legacy_secs / integer_secs);
+}
@@ -0,0 +1,60 @@
+use std::{hint::black_box, time::Instant};
+
+use intcode::{block::Engine, int::{Computer, ComputerState}, program::Program};
+
+const BOOST_PROG: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../../aoc19/aoc9/prog.txt"));
+const BEAM_PROG: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../../aoc19/aoc19/prog.txt"));
+const RUNS: u32 = 10;
+const PROBES: i64 = 100;
+
+// runs BOOST in sensor boost mode on fresh machines, returns seconds
+fn boost(mut comp: impl FnMut() -> Computer) -> f64 {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let mut comp = comp().with_input([2]);
+        while let Ok(ComputerState::WriteInt) = comp.run() {}
+        assert_eq!(comp.outs.len(), 1);
+        black_box(comp.outs);
+    }
+    start.elapsed().as_secs_f64()
+}
+
+// probes a square of the tractor beam forking `template` per point, returns seconds
+fn beam(mut template: Computer) -> f64 {
+    let start = Instant::now();
+    for y in 0..PROBES {
+        for x in 0..PROBES {
+            let mut comp = template.clone().with_input([x, y]);
+            comp.run().unwrap();
+            template.adopt_blocks(&comp);
+            black_box(comp.outs);
+        }
+    }
+    start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let prog: Program = BOOST_PROG.parse().unwrap();
+    let mut warm = Computer::new(&prog).with_input([2]).with_engine(Engine::Blocks);
+    while let Ok(ComputerState::WriteInt) = warm.run() {}
+    let cache = warm.block_cache().unwrap().clone();
+
+    let interpreter_secs = boost(|| Computer::new(&prog));
+    let blocks_secs = boost(|| Computer::new(&prog).with_engine(Engine::Blocks));
+    let warm_secs = boost(|| Computer::new(&prog).with_block_cache(cache.clone()));
+
+    println!("BOOST x{} ({} blocks)", RUNS, cache.len());
+    println!("interpreter  {:>8.2} ms/run", interpreter_secs * 1000.0 / RUNS as f64);
+    println!("blocks       {:>8.2} ms/run  {:.2}x", blocks_secs * 1000.0 / RUNS as f64, interpreter_secs / blocks_secs);
+    println!("warm blocks  {:>8.2} ms/run  {:.2}x", warm_secs * 1000.0 / RUNS as f64, interpreter_secs / warm_secs);
+
+    // a few hundred instructions per run that patch their own code,
+    // too short for compiling to pay off
+    let prog: Program = BEAM_PROG.parse().unwrap();
+    let interpreter_secs = beam(Computer::new(&prog));
+    let blocks_secs = beam(Computer::new(&prog).with_engine(Engine::Blocks));
+    let probes = (PROBES * PROBES) as f64;
+    println!("beam probes x{}", probes);
+    println!("interpreter  {:>8.2} us/run", interpreter_secs * 1e6 / probes);
+    println!("blocks       {:>8.2} us/run  {:.2}x", blocks_secs * 1e6 / probes, interpreter_secs / blocks_secs);
+}
@@ -0,0 +1,402 @@
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use crate::{
+    int::{Computer, ComputerState, VmError},
+    program::Program,
+    trace::Tracer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tile {
+    #[default]
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+
+impl Tile {
+    pub fn from_id(id: i64) -> Option<Tile> {
+        Some(match id {
+            0 => Tile::Empty,
+            1 => Tile::Wall,
+            2 => Tile::Block,
+            3 => Tile::Paddle,
+            4 => Tile::Ball,
+            _ => return None,
+        })
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Wall => 'W',
+            Tile::Block => 'B',
+            Tile::Paddle => '_',
+            Tile::Ball => 'O',
+        }
+    }
+}
+
+/// Largest width or height a screen grows to.
+pub const MAX_SCREEN_SIDE: usize = 1 << 12;
+
+/// Tiles drawn so far, growing to fit whatever the game draws. Tiles past
+/// `MAX_SCREEN_SIDE` are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Screen {
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+}
+
+impl Screen {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Tile {
+        if x < self.width && y < self.height {
+            self.tiles[y * self.width + x]
+        } else {
+            Tile::Empty
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, tile: Tile) {
+        if x >= MAX_SCREEN_SIDE || y >= MAX_SCREEN_SIDE {
+            return;
+        }
+        if x >= self.width || y >= self.height {
+            let (width, height) = (self.width.max(x + 1), self.height.max(y + 1));
+            let mut tiles = vec![Tile::Empty; width * height];
+            for (row, old) in self.tiles.chunks(self.width.max(1)).enumerate() {
+                tiles[row * width..row * width + old.len()].copy_from_slice(old);
+            }
+            (self.width, self.height, self.tiles) = (width, height, tiles);
+        }
+        self.tiles[y * self.width + x] = tile;
+    }
+
+    pub fn count(&self, tile: Tile) -> usize {
+        self.tiles.iter().filter(|&&t| t == tile).count()
+    }
+}
+
+impl fmt::Display for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.tiles.chunks(self.width.max(1)) {
+            let line: String = row.iter().map(|t| t.to_char()).collect();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Picks the joystick position every time the game asks for it: -1 tilts
+/// left, 0 stays, 1 tilts right.
+pub trait Controller {
+    fn joystick(&mut self, arcade: &Arcade) -> i64;
+}
+
+impl<T: Controller + ?Sized> Controller for Box<T> {
+    fn joystick(&mut self, arcade: &Arcade) -> i64 {
+        (**self).joystick(arcade)
+    }
+}
+
+/// Keeps the joystick neutral.
+impl Controller for () {
+    fn joystick(&mut self, _arcade: &Arcade) -> i64 {
+        0
+    }
+}
+
+/// Moves the paddle under the ball.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FollowBall;
+
+impl Controller for FollowBall {
+    fn joystick(&mut self, arcade: &Arcade) -> i64 {
+        match (arcade.ball, arcade.paddle) {
+            (Some(ball), Some(paddle)) => (ball.0 - paddle.0).signum(),
+            _ => 0,
+        }
+    }
+}
+
+/// Moves the paddle to where the ball will next come down to the paddle
+/// row, following its trajectory on a copy of the screen as it bounces off
+/// walls and breaks blocks. The guess is redone every frame.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PredictLanding;
+
+impl PredictLanding {
+    fn landing(arcade: &Arcade) -> Option<i64> {
+        let (ball, prev, paddle) = (arcade.ball?, arcade.prev_ball?, arcade.paddle?);
+        let (mut x, mut y) = ball;
+        let (mut dx, mut dy) = (ball.0 - prev.0, ball.1 - prev.1);
+        if dx.abs() != 1 || dy.abs() != 1 {
+            return None;
+        }
+        let mut screen = arcade.screen.clone();
+        let mut solid = |x: i64, y: i64| {
+            let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) else {
+                return true;
+            };
+            match screen.get(x, y) {
+                Tile::Wall => true,
+                Tile::Block => {
+                    screen.set(x, y, Tile::Empty);
+                    true
+                }
+                _ => false,
+            }
+        };
+        // every step breaks a block or moves the ball, so this covers both
+        for _ in 0..4 * arcade.screen.width() * arcade.screen.height() {
+            if dy > 0 && y + 1 >= paddle.1 {
+                return Some(x);
+            }
+            let side = solid(x + dx, y);
+            let vertical = solid(x, y + dy);
+            if side || vertical {
+                dx = if side { -dx } else { dx };
+                dy = if vertical { -dy } else { dy };
+            } else if solid(x + dx, y + dy) {
+                (dx, dy) = (-dx, -dy);
+            } else {
+                (x, y) = (x + dx, y + dy);
+            }
+        }
+        None
+    }
+}
+
+impl Controller for PredictLanding {
+    fn joystick(&mut self, arcade: &Arcade) -> i64 {
+        let Some(paddle) = arcade.paddle else { return 0 };
+        let target = PredictLanding::landing(arcade).or(arcade.ball.map(|b| b.0));
+        target.map_or(0, |x| (x - paddle.0).signum())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Drew the frame and waits for the joystick.
+    Waiting,
+    Over,
+}
+
+/// The arcade cabinet: runs the game, keeps the screen and score up to
+/// date from the `(x, y, tile)` triples it outputs, and asks a controller
+/// for the joystick once per frame.
+pub struct Arcade {
+    pub comp: Computer,
+    pub screen: Screen,
+    pub score: i64,
+    pub ball: Option<(i64, i64)>,
+    /// Ball position a frame earlier, for its direction.
+    pub prev_ball: Option<(i64, i64)>,
+    pub paddle: Option<(i64, i64)>,
+    /// Frames drawn, one per joystick read.
+    pub frame: u64,
+}
+
+impl Arcade {
+    /// Free play isn't enabled, the game draws the screen and halts.
+    pub fn new(prog: &Program) -> Arcade {
+        Arcade::from_computer(Computer::new(prog))
+    }
+
+    /// Inserts coins first, writing address 0.
+    pub fn with_coins(prog: &Program, coins: i64) -> Arcade {
+        Arcade::from_computer(Computer::new(prog).with_patch(0, coins))
+    }
+
+    pub fn from_computer(comp: Computer) -> Arcade {
+        Arcade { comp, screen: Screen::default(), score: 0, ball: None, prev_ball: None, paddle: None, frame: 0 }
+    }
+
+    pub fn blocks(&self) -> usize {
+        self.screen.count(Tile::Block)
+    }
+
+    fn draw(&mut self, x: i64, y: i64, id: i64) {
+        if (x, y) == (-1, 0) {
+            self.score = id;
+            return;
+        }
+        let tile = Tile::from_id(id).unwrap_or_default();
+        match tile {
+            Tile::Ball => self.ball = Some((x, y)),
+            Tile::Paddle => self.paddle = Some((x, y)),
+            _ => {}
+        }
+        if let (Ok(x), Ok(y)) = (usize::try_from(x), usize::try_from(y)) {
+            self.screen.set(x, y, tile);
+        }
+    }
+
+    /// Runs the game until it has drawn the next frame or it's over.
+    pub fn run_frame(&mut self) -> Result<Status, VmError> {
+        self.run_frame_with(&mut Computer::run)
+    }
+
+    fn run_frame_with(
+        &mut self,
+        run: &mut impl FnMut(&mut Computer) -> Result<ComputerState, VmError>,
+    ) -> Result<Status, VmError> {
+        let ball = self.ball;
+        loop {
+            let state = run(&mut self.comp)?;
+            while self.comp.outs.len() >= 3 {
+                let tile: Vec<i64> = self.comp.outs.drain(..3).collect();
+                self.draw(tile[0], tile[1], tile[2]);
+            }
+            match state {
+                ComputerState::WriteInt | ComputerState::BudgetExhausted => {}
+                ComputerState::ReadInt => {
+                    self.prev_ball = ball;
+                    self.frame += 1;
+                    return Ok(Status::Waiting);
+                }
+                ComputerState::Halted => return Ok(Status::Over),
+            }
+        }
+    }
+
+    /// Plays until the game is over, returns the final score.
+    pub fn play(&mut self, ctrl: &mut impl Controller) -> Result<i64, VmError> {
+        self.play_observed(ctrl, |_| {})
+    }
+
+    /// `play` that hands every frame to `observe` before moving the joystick.
+    pub fn play_observed(&mut self, ctrl: &mut impl Controller, mut observe: impl FnMut(&Arcade)) -> Result<i64, VmError> {
+        while self.run_frame()? == Status::Waiting {
+            observe(self);
+            let joystick = ctrl.joystick(self);
+            self.comp.ins.push_back(joystick);
+        }
+        observe(self);
+        Ok(self.score)
+    }
+
+    /// `play` reporting every executed instruction to `tracer`.
+    pub fn play_traced(&mut self, ctrl: &mut impl Controller, tracer: &mut impl Tracer) -> Result<i64, VmError> {
+        while self.run_frame_with(&mut |comp: &mut Computer| comp.run_traced(tracer))? == Status::Waiting {
+            let joystick = ctrl.joystick(self);
+            self.comp.ins.push_back(joystick);
+        }
+        Ok(self.score)
+    }
+}
+
+impl fmt::Display for Arcade {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "frame {} score {} blocks {}", self.frame, self.score, self.blocks())?;
+        write!(f, "{}", self.screen)
+    }
+}
+
+/// Writes every frame it's shown as text, the frame, score and block
+/// count line followed by the screen. The first write error is kept in
+/// `error` and stops further writes.
+pub struct FrameLog<W: Write> {
+    out: W,
+    pub error: Option<io::Error>,
+}
+
+impl<W: Write> FrameLog<W> {
+    pub fn new(out: W) -> FrameLog<W> {
+        FrameLog { out, error: None }
+    }
+
+    pub fn record(&mut self, arcade: &Arcade) {
+        if self.error.is_none() {
+            if let Err(e) = write!(self.out, "{}", arcade) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush().map(|_| self.out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_util::aoc_program, trace::Profile};
+
+    fn breakout() -> Program {
+        aoc_program!(13)
+    }
+
+    #[test]
+    fn test_screen_grows() {
+        let mut screen = Screen::default();
+        screen.set(1, 0, Tile::Wall);
+        screen.set(0, 2, Tile::Ball);
+        screen.set(3, 1, Tile::Block);
+        assert_eq!((screen.width(), screen.height()), (4, 3));
+        assert_eq!(screen.get(1, 0), Tile::Wall);
+        assert_eq!(screen.get(0, 2), Tile::Ball);
+        assert_eq!(screen.get(9, 9), Tile::Empty);
+        assert_eq!(screen.to_string(), " W  \n   B\nO   \n");
+        screen.set(usize::MAX, 0, Tile::Wall);
+        screen.set(0, MAX_SCREEN_SIDE, Tile::Wall);
+        assert_eq!((screen.width(), screen.height()), (4, 3));
+    }
+
+    #[test]
+    fn test_demo_screen() {
+        let mut arcade = Arcade::new(&breakout());
+        assert_eq!(arcade.play(&mut ()), Ok(0));
+        assert_eq!(arcade.blocks(), 260);
+        assert_eq!((arcade.screen.width(), arcade.screen.height()), (35, 23));
+        assert_eq!(arcade.frame, 0);
+    }
+
+    #[test]
+    fn test_controllers_win() {
+        let mut follow = Arcade::with_coins(&breakout(), 2);
+        assert_eq!(follow.play(&mut FollowBall), Ok(12952));
+        assert_eq!(follow.blocks(), 0);
+
+        let mut predict = Arcade::with_coins(&breakout(), 2);
+        assert_eq!(predict.play(&mut PredictLanding), Ok(12952));
+        assert_eq!(predict.blocks(), 0);
+
+        let mut traced = Arcade::with_coins(&breakout(), 2);
+        let mut profile = Profile::new();
+        assert_eq!(traced.play_traced(&mut PredictLanding, &mut profile), Ok(12952));
+        assert_eq!(traced.frame, predict.frame);
+        assert_eq!(traced.screen.to_string(), predict.screen.to_string());
+        assert!(profile.steps > 0);
+    }
+
+    #[test]
+    fn test_frame_log() {
+        let mut arcade = Arcade::with_coins(&breakout(), 2);
+        let mut log = FrameLog::new(vec![]);
+        arcade.play_observed(&mut FollowBall, |a| log.record(a)).unwrap();
+        let text = String::from_utf8(log.finish().unwrap()).unwrap();
+        let headers: Vec<&str> = text.lines().filter(|l| l.starts_with("frame")).collect();
+        assert_eq!(headers.len() as u64, arcade.frame + 1);
+        assert_eq!(headers[0], "frame 1 score 0 blocks 260");
+        assert!(headers.last().unwrap().ends_with("score 12952 blocks 0"));
+    }
+}
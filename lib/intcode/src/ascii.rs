@@ -0,0 +1,172 @@
+use std::io::{self, BufRead, Write};
+
+use crate::int::{Computer, ComputerState, VmError};
+
+/// Character for an output word, `None` for values outside ASCII, which
+/// ASCII programs use for their final answer.
+pub fn ascii_char(val: i64) -> Option<char> {
+    u8::try_from(val).ok().filter(u8::is_ascii).map(char::from)
+}
+
+/// Text interface over a machine that talks ASCII, like the aoc17 vacuum
+/// robot or the aoc21 springdroid.
+pub struct AsciiComputer {
+    pub comp: Computer,
+    /// Non-ASCII values written so far, kept out of the text.
+    pub values: Vec<i64>,
+    text: String,
+    state: ComputerState,
+}
+
+impl AsciiComputer {
+    pub fn new(comp: Computer) -> AsciiComputer {
+        AsciiComputer {
+            comp,
+            values: vec![],
+            text: String::new(),
+            state: ComputerState::ReadInt,
+        }
+    }
+
+    /// State the machine stopped in last.
+    pub fn state(&self) -> ComputerState {
+        self.state
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.state == ComputerState::Halted
+    }
+
+    pub fn send(&mut self, text: &str) {
+        self.comp.ins.extend(text.bytes().map(i64::from));
+    }
+
+    pub fn send_line(&mut self, line: &str) {
+        self.send(line);
+        self.comp.ins.push_back(i64::from(b'\n'));
+    }
+
+    // runs until the next stop and sorts the outputs into text and values
+    fn run(&mut self) -> Result<ComputerState, VmError> {
+        self.state = self.comp.run()?;
+        for val in self.comp.outs.drain(..) {
+            match ascii_char(val) {
+                Some(ch) => self.text.push(ch),
+                None => self.values.push(val),
+            }
+        }
+        Ok(self.state)
+    }
+
+    /// Next full line without its newline. `None` once the machine halts or
+    /// waits for input before finishing a line, the partial line stays
+    /// buffered for the next read.
+    pub fn read_line(&mut self) -> Result<Option<String>, VmError> {
+        loop {
+            if let Some(end) = self.text.find('\n') {
+                let line = self.text[..end].to_owned();
+                self.text.drain(..=end);
+                return Ok(Some(line));
+            }
+            if self.run()? != ComputerState::WriteInt {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// All text written until the machine halts or waits for input,
+    /// including an unfinished prompt line.
+    pub fn read_until_prompt(&mut self) -> Result<String, VmError> {
+        while self.run()? == ComputerState::WriteInt {}
+        Ok(std::mem::take(&mut self.text))
+    }
+
+    /// Plays the program from a terminal: prints its text, sends each line
+    /// read from `input`, and prints values as they arrive. Returns once the
+    /// machine halts or `input` ends.
+    pub fn interact(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut printed = self.values.len();
+        loop {
+            let text = self.read_until_prompt().map_err(io::Error::other)?;
+            write!(output, "{}", text)?;
+            for val in self.values[printed..].iter() {
+                writeln!(output, "{}", val)?;
+            }
+            printed = self.values.len();
+            output.flush()?;
+            if self.is_halted() {
+                return Ok(());
+            }
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            self.send_line(line.trim_end_matches(['\r', '\n']));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, program::Program};
+
+    // prints a prompt, echoes one line back in upper case, then writes 1000
+    const SHOUT: &str = "
+            out #72
+            out #105
+            out #10
+            out #62
+            out #32
+        loop: in [ch]
+            eq [ch], #10, [tmp]
+            jt [tmp], #done
+            lt [ch], #97, [tmp]
+            jt [tmp], #emit
+            add [ch], #-32, [ch]
+        emit: out [ch]
+            jf #0, #loop
+        done: out #10
+            out #1000
+            hlt
+        ch: .data 0
+        tmp: .data 0
+    ";
+
+    fn shout() -> AsciiComputer {
+        AsciiComputer::new(Computer::new(&Program::from(assemble(SHOUT).unwrap().code)))
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut ascii = shout();
+        assert_eq!(ascii.read_line(), Ok(Some("Hi".to_owned())));
+        assert_eq!(ascii.read_line(), Ok(None));
+        assert_eq!(ascii.state(), ComputerState::ReadInt);
+        ascii.send_line("hey you");
+        assert_eq!(ascii.read_line(), Ok(Some("> HEY YOU".to_owned())));
+        assert_eq!(ascii.read_line(), Ok(None));
+        assert!(ascii.is_halted());
+        assert_eq!(ascii.values, [1000]);
+    }
+
+    #[test]
+    fn test_prompt_and_interact() {
+        let mut ascii = shout();
+        assert_eq!(ascii.read_until_prompt(), Ok("Hi\n> ".to_owned()));
+
+        let mut ascii = shout();
+        let mut out = vec![];
+        ascii.interact(&b"abc\r\nignored\n"[..], &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Hi\n> ABC\n1000\n");
+    }
+
+    #[test]
+    fn test_ascii_char() {
+        assert_eq!(ascii_char(65), Some('A'));
+        assert_eq!(ascii_char(10), Some('\n'));
+        assert_eq!(ascii_char(128), None);
+        assert_eq!(ascii_char(-1), None);
+        assert_eq!(ascii_char(1140850168), None);
+    }
+}
@@ -0,0 +1,296 @@
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    int::{Instruction, OpCode, OpParamMode, OpParamType},
+    mem::DEFAULT_LIMIT,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    UnknownDirective(String),
+    BadOperand(String),
+    OperandCount { expected: usize, found: usize },
+    ImmediateWrite,
+    UnknownLabel(String),
+    DuplicateLabel(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(m) => write!(f, "unknown mnemonic \"{}\"", m),
+            AsmErrorKind::UnknownDirective(d) => write!(f, "unknown directive \"{}\"", d),
+            AsmErrorKind::BadOperand(o) => write!(f, "bad operand \"{}\"", o),
+            AsmErrorKind::OperandCount { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            AsmErrorKind::ImmediateWrite => write!(f, "write operand in immediate mode"),
+            AsmErrorKind::UnknownLabel(l) => write!(f, "unknown label \"{}\"", l),
+            AsmErrorKind::DuplicateLabel(l) => write!(f, "duplicate label \"{}\"", l),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(i64),
+    Label(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+    Instr { code: OpCode, operands: Vec<(OpParamMode, Value)> },
+    Data(Vec<Value>),
+    Zero(usize),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instr { code, .. } => code.size(),
+            Statement::Data(values) => values.len(),
+            Statement::Zero(n) => *n,
+        }
+    }
+}
+
+/// Assembled program together with the listing of every source line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assembly {
+    pub code: Vec<i64>,
+    /// Address, emitted words and source text of each statement.
+    pub lines: Vec<(usize, Vec<i64>, String)>,
+}
+
+impl fmt::Display for Assembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, words, text) in self.lines.iter() {
+            let raw: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+            writeln!(f, "{:>6}: {:<28} {}", addr, raw.join(","), text)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(s: &str) -> Option<Value> {
+    if let Ok(val) = s.parse::<i64>() {
+        Some(Value::Int(val))
+    } else if is_label(s) {
+        Some(Value::Label(s.to_owned()))
+    } else {
+        None
+    }
+}
+
+fn parse_operand(s: &str) -> Option<(OpParamMode, Value)> {
+    if let Some(imm) = s.strip_prefix('#') {
+        Some((OpParamMode::Immediate, parse_value(imm.trim())?))
+    } else if let Some(pos) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        Some((OpParamMode::Position, parse_value(pos.trim())?))
+    } else if let Some(off) = s.strip_prefix("rb") {
+        let off = off.replace(' ', "");
+        let val = match off.strip_prefix('+') {
+            _ if off.is_empty() => 0,
+            Some(pos) => pos.parse().ok()?,
+            None => off.parse().ok().filter(|v: &i64| *v < 0)?,
+        };
+        Some((OpParamMode::Relative, Value::Int(val)))
+    } else {
+        None
+    }
+}
+
+fn split_list(s: &str) -> Vec<&str> {
+    if s.trim().is_empty() {
+        vec![]
+    } else {
+        s.split(',').map(|t| t.trim()).collect()
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, AsmErrorKind> {
+    let (head, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    match head {
+        ".data" => split_list(rest).into_iter()
+            .map(|v| parse_value(v).ok_or_else(|| AsmErrorKind::BadOperand(v.to_owned())))
+            .collect::<Result<_, _>>()
+            .map(Statement::Data),
+        ".zero" => rest.trim().parse().map(Statement::Zero)
+            .map_err(|_| AsmErrorKind::BadOperand(rest.trim().to_owned())),
+        _ if head.starts_with('.') => Err(AsmErrorKind::UnknownDirective(head.to_owned())),
+        _ => {
+            let code = OpCode::from_mnemonic(head)
+                .ok_or_else(|| AsmErrorKind::UnknownMnemonic(head.to_owned()))?;
+            let operands: Vec<_> = split_list(rest).into_iter()
+                .map(|o| parse_operand(o).ok_or_else(|| AsmErrorKind::BadOperand(o.to_owned())))
+                .collect::<Result<_, _>>()?;
+            let types = code.param_types();
+            if operands.len() != types.len() {
+                return Err(AsmErrorKind::OperandCount { expected: types.len(), found: operands.len() });
+            }
+            let immediate_write = types.iter().zip(operands.iter())
+                .any(|(t, (mode, _))| *t == OpParamType::Write && *mode == OpParamMode::Immediate);
+            if immediate_write {
+                return Err(AsmErrorKind::ImmediateWrite);
+            }
+            Ok(Statement::Instr { code, operands })
+        }
+    }
+}
+
+/// Assembles mnemonic source into Intcode.
+///
+/// Each line holds an optional `label:`, then an instruction like
+/// `add #1, [20], rb-2` or a directive, `.data 1, 2, label` or `.zero 10`.
+/// Comments start with `;`.
+pub fn assemble(src: &str) -> Result<Assembly, AsmError> {
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    let mut statements = vec![];
+    let mut addr = 0;
+    for (i, line) in src.lines().enumerate() {
+        let line_no = i + 1;
+        let mut text = line.split(';').next().unwrap().trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if is_label(label) {
+                if labels.insert(label.to_owned(), addr).is_some() {
+                    return Err(AsmError { line: line_no, kind: AsmErrorKind::DuplicateLabel(label.to_owned()) });
+                }
+                text = rest.trim();
+            }
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let statement = parse_statement(text).map_err(|kind| AsmError { line: line_no, kind })?;
+        // zeros past what the machine can address would only be allocated to fail
+        if let Statement::Zero(n) = statement {
+            if n > DEFAULT_LIMIT.saturating_sub(addr) {
+                return Err(AsmError { line: line_no, kind: AsmErrorKind::BadOperand(n.to_string()) });
+            }
+        }
+        addr += statement.size();
+        statements.push((line_no, statement, text));
+    }
+
+    let resolve = |line: usize, val: &Value| -> Result<i64, AsmError> {
+        match val {
+            Value::Int(v) => Ok(*v),
+            Value::Label(l) => labels.get(l)
+                .map(|&a| a as i64)
+                .ok_or_else(|| AsmError { line, kind: AsmErrorKind::UnknownLabel(l.clone()) }),
+        }
+    };
+    let mut code = Vec::with_capacity(addr);
+    let mut lines = Vec::with_capacity(statements.len());
+    for (line_no, statement, text) in statements {
+        let start = code.len();
+        match statement {
+            Statement::Instr { code: op_code, operands } => {
+                let mut modes = [OpParamMode::Position; 3];
+                for (i, (mode, _)) in operands.iter().enumerate() {
+                    modes[i] = *mode;
+                }
+                code.push(Instruction { code: op_code, modes }.encode());
+                for (_, val) in operands.iter() {
+                    code.push(resolve(line_no, val)?);
+                }
+            }
+            Statement::Data(values) => {
+                for val in values.iter() {
+                    code.push(resolve(line_no, val)?);
+                }
+            }
+            Statement::Zero(n) => code.resize(start + n, 0),
+        }
+        lines.push((start, code[start..].to_vec(), text.to_owned()));
+    }
+    Ok(Assembly { code, lines })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{disasm::disassemble, int::{Computer, ComputerState}, program::Program, test_util::boost};
+
+    fn run_outputs(code: &[i64], input: i64) -> Vec<i64> {
+        let mut comp = Computer::new(&Program::from(code)).with_input([input]);
+        while comp.run() == Ok(ComputerState::WriteInt) {}
+        comp.outs.into_iter().collect()
+    }
+
+    #[test]
+    fn test_assemble() {
+        let asm = assemble("
+            ; prints 3, 2, 1
+                add #3, #0, [counter]
+            loop: out [counter]
+                add #-1, [counter], [counter]
+                jt [counter], #loop
+                hlt
+            counter: .data 0
+        ").unwrap();
+        assert_eq!(asm.code, [1101, 3, 0, 14, 4, 14, 101, -1, 14, 14, 1005, 14, 4, 99, 0]);
+        assert_eq!(run_outputs(&asm.code, 0), [3, 2, 1]);
+        assert_eq!(asm.to_string().lines().nth(1).unwrap(),
+            "     4: 4,14                         out [counter]");
+    }
+
+    #[test]
+    fn test_relative_and_directives() {
+        let asm = assemble("arb #10\nin rb-2\nout rb + 3\nout rb\n.zero 3\n.data -1, end\nend: hlt").unwrap();
+        assert_eq!(asm.code, [109, 10, 203, -2, 204, 3, 204, 0, 0, 0, 0, -1, 13, 99]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |src: &str| assemble(src).unwrap_err();
+        assert_eq!(err("nop"), AsmError { line: 1, kind: AsmErrorKind::UnknownMnemonic("nop".to_owned()) });
+        assert_eq!(err("hlt\nadd #1, #2"), AsmError { line: 2, kind: AsmErrorKind::OperandCount { expected: 3, found: 2 } });
+        assert_eq!(err("in #5"), AsmError { line: 1, kind: AsmErrorKind::ImmediateWrite });
+        assert_eq!(err("jt #1, #nowhere"), AsmError { line: 1, kind: AsmErrorKind::UnknownLabel("nowhere".to_owned()) });
+        assert_eq!(err("a: hlt\na: hlt"), AsmError { line: 2, kind: AsmErrorKind::DuplicateLabel("a".to_owned()) });
+        assert_eq!(err("out {1}"), AsmError { line: 1, kind: AsmErrorKind::BadOperand("{1}".to_owned()) });
+        assert_eq!(err(".byte 1"), AsmError { line: 1, kind: AsmErrorKind::UnknownDirective(".byte".to_owned()) });
+        let huge = usize::MAX.to_string();
+        assert_eq!(err(&format!("hlt\n.zero {}", huge)), AsmError { line: 2, kind: AsmErrorKind::BadOperand(huge) });
+        assert_eq!(err(&format!("hlt\n.zero {}", DEFAULT_LIMIT)).kind, AsmErrorKind::BadOperand(DEFAULT_LIMIT.to_string()));
+    }
+
+    #[test]
+    fn test_boost_round_trip() {
+        let boost = boost();
+        let source = disassemble(&boost.code).source();
+        let asm = assemble(&source).unwrap();
+        assert_eq!(asm.code, boost.code);
+        // BOOST test mode only outputs the keycode when every opcode check passes
+        assert_eq!(run_outputs(&asm.code, 1), run_outputs(&boost.code, 1));
+        assert_eq!(run_outputs(&asm.code, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_non_canonical_round_trip() {
+        // modes past the param count still run, so they have to survive
+        for code in [vec![1099], vec![21104, 5, 99], vec![104, 1, 1099]] {
+            let source = disassemble(&code).source();
+            assert_eq!(assemble(&source).unwrap().code, code, "{}", source);
+        }
+        assert_eq!(disassemble(&[104, 1, 1099]).source(), "    out #1\n    .data 1099\n");
+    }
+}
@@ -0,0 +1,19 @@
+use std::{env, io, process};
+
+use intcode::{ascii::AsciiComputer, int::Computer, program::Program};
+
+fn main() {
+    let Some(prog_path) = env::args().nth(1) else {
+        eprintln!("usage: intascii <prog.txt>");
+        process::exit(2);
+    };
+    let prog = Program::load(&prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    let mut ascii = AsciiComputer::new(Computer::new(&prog));
+    if let Err(e) = ascii.interact(io::stdin().lock(), io::stdout().lock()) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
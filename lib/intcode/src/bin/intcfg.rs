@@ -0,0 +1,27 @@
+use std::{env, process};
+
+use intcode::{cfg::Cfg, program::Program};
+
+const USAGE: &str = "usage: intcfg <prog.txt> [--calls]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (prog_path, calls) = match &args[..] {
+        [path] => (path, false),
+        [path, flag] if flag == "--calls" => (path, true),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let prog = Program::load(prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    let cfg = Cfg::new(&prog.code);
+    if calls {
+        print!("{}", cfg.call_graph_dot());
+    } else {
+        print!("{}", cfg.to_dot());
+    }
+}
@@ -0,0 +1,222 @@
+use std::{env, io::{self, BufRead, Write}, process};
+
+use intcode::{
+    debugger::{Breakpoint, Debugger, Stop},
+    disasm::{decode_at, format_operand},
+    int::Computer,
+    program::Program,
+};
+
+const HELP: &str = "\
+commands:
+  break <addr> [if <addr> == <val>]  stop at an address, optionally only while memory holds a value
+  break if <addr> == <val>           stop as soon as memory holds a value
+  watch <addr> / unwatch <addr>      stop when an instruction writes to an address
+  delete [n]                         remove breakpoint n, or all of them
+  info                               list breakpoints and watchpoints
+  step [n]                           execute n instructions, default 1
+  continue                           run until something stops execution
+  mem <addr>[..<end>]                print memory
+  set <addr> <val>                   write memory
+  regs                               print registers and the next instruction
+  list [addr] [n]                    disassemble n instructions, default at eip
+  input <val>...                     queue input values
+  quit";
+
+fn parse_addr(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("bad address \"{}\"", s))
+}
+
+fn parse_val(s: &str) -> Result<i64, String> {
+    s.parse().map_err(|_| format!("bad value \"{}\"", s))
+}
+
+// "<addr> == <val>"
+fn parse_cond(args: &[&str]) -> Result<(usize, i64), String> {
+    match args {
+        [addr, "==", val] => Ok((parse_addr(addr)?, parse_val(val)?)),
+        _ => Err("expected <addr> == <val>".to_owned()),
+    }
+}
+
+fn parse_breakpoint(args: &[&str]) -> Result<Breakpoint, String> {
+    match args {
+        ["if", cond @ ..] => {
+            let (addr, val) = parse_cond(cond)?;
+            Ok(Breakpoint::MemEquals { addr, val })
+        }
+        [eip] => Ok(Breakpoint::Addr(parse_addr(eip)?)),
+        [eip, "if", cond @ ..] => {
+            let (addr, val) = parse_cond(cond)?;
+            Ok(Breakpoint::AddrIf { eip: parse_addr(eip)?, addr, val })
+        }
+        _ => Err("usage: break <addr> [if <addr> == <val>]".to_owned()),
+    }
+}
+
+/// Disassembles the instruction at `addr`, returning its text and size.
+fn instr_at(comp: &Computer, addr: usize) -> Option<(String, usize)> {
+    let window: Vec<i64> = (addr..addr.saturating_add(4)).map(|a| comp.mem.get(a)).collect();
+    let (instr, args) = decode_at(&window, 0)?;
+    let operands: Vec<String> = instr.modes.iter()
+        .zip(args)
+        .map(|(&mode, &val)| format_operand(mode, val))
+        .collect();
+    Some((format!("{} {}", instr.code.mnemonic(), operands.join(", ")).trim_end().to_owned(), instr.code.size()))
+}
+
+fn print_stop(dbg: &Debugger, stop: Stop) {
+    match stop {
+        Stop::Breakpoint(bp) => println!("breakpoint {:?}", bp),
+        Stop::Watchpoint { eip, addr, old, new } => {
+            println!("watchpoint [{}]: {} -> {} by instruction at {}", addr, old, new, eip)
+        }
+        Stop::NeedInput => println!("waiting for input"),
+        Stop::Halted => println!("halted"),
+    }
+    print_next(dbg);
+}
+
+fn print_next(dbg: &Debugger) {
+    match instr_at(&dbg.comp, dbg.comp.eip) {
+        Some((text, _)) => println!("{:>6}: {}", dbg.comp.eip, text),
+        None => println!("{:>6}: ??? {}", dbg.comp.eip, dbg.comp.mem.get(dbg.comp.eip)),
+    }
+}
+
+fn exec(dbg: &mut Debugger, cmd: &str, args: &[&str]) -> Result<(), String> {
+    match cmd {
+        "help" | "h" => println!("{}", HELP),
+        "break" | "b" => {
+            let bp = parse_breakpoint(args)?;
+            dbg.add_breakpoint(bp);
+            println!("breakpoint {}: {:?}", dbg.breakpoints.len() - 1, bp);
+        }
+        "watch" | "w" => dbg.watch(parse_addr(args.first().ok_or("usage: watch <addr>")?)?),
+        "unwatch" => {
+            let addr = parse_addr(args.first().ok_or("usage: unwatch <addr>")?)?;
+            if !dbg.unwatch(addr) {
+                return Err(format!("no watchpoint at {}", addr));
+            }
+        }
+        "delete" | "d" => match args.first() {
+            Some(n) => {
+                let n = parse_addr(n)?;
+                if n >= dbg.breakpoints.len() {
+                    return Err(format!("no breakpoint {}", n));
+                }
+                dbg.breakpoints.remove(n);
+            }
+            None => dbg.breakpoints.clear(),
+        },
+        "info" | "i" => {
+            for (n, bp) in dbg.breakpoints.iter().enumerate() {
+                println!("breakpoint {}: {:?}", n, bp);
+            }
+            for addr in dbg.watchpoints.iter() {
+                println!("watchpoint [{}]", addr);
+            }
+        }
+        "step" | "s" => {
+            let n = args.first().map(|n| parse_addr(n)).transpose()?.unwrap_or(1);
+            for _ in 0..n {
+                if let Some(stop) = dbg.step().map_err(|e| e.to_string())? {
+                    print_stop(dbg, stop);
+                    return Ok(());
+                }
+            }
+            print_next(dbg);
+        }
+        "continue" | "c" => {
+            let stop = dbg.run().map_err(|e| e.to_string())?;
+            print_stop(dbg, stop);
+        }
+        "mem" | "m" => {
+            let range = args.first().ok_or("usage: mem <addr>[..<end>]")?;
+            let (start, end) = match range.split_once("..") {
+                Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+                None => {
+                    let addr = parse_addr(range)?;
+                    (addr, addr.saturating_add(1))
+                }
+            };
+            for row in (start..end).step_by(8) {
+                let vals: Vec<String> = (row..end.min(row.saturating_add(8))).map(|a| dbg.comp.mem.get(a).to_string()).collect();
+                println!("{:>6}: {}", row, vals.join(" "));
+            }
+        }
+        "set" => match args {
+            [addr, val] => dbg.comp.mem.set(parse_addr(addr)?, parse_val(val)?)
+                .map_err(|e| format!("address {} over memory limit {}", e.addr, e.limit))?,
+            _ => return Err("usage: set <addr> <val>".to_owned()),
+        },
+        "regs" | "r" => {
+            println!("eip={} esp={} steps={} ins={:?}", dbg.comp.eip, dbg.comp.esp, dbg.steps, dbg.comp.ins);
+            print_next(dbg);
+        }
+        "list" | "l" => {
+            let mut addr = args.first().map(|a| parse_addr(a)).transpose()?.unwrap_or(dbg.comp.eip);
+            let n = args.get(1).map(|n| parse_addr(n)).transpose()?.unwrap_or(10);
+            for _ in 0..n {
+                match instr_at(&dbg.comp, addr) {
+                    Some((text, size)) => {
+                        println!("{:>6}: {}", addr, text);
+                        addr = addr.saturating_add(size);
+                    }
+                    None => {
+                        println!("{:>6}: .data {}", addr, dbg.comp.mem.get(addr));
+                        addr = addr.saturating_add(1);
+                    }
+                }
+            }
+        }
+        "input" => {
+            let vals = args.iter().map(|v| parse_val(v)).collect::<Result<Vec<_>, _>>()?;
+            dbg.comp.ins.extend(vals);
+        }
+        _ => return Err(format!("unknown command \"{}\", try help", cmd)),
+    }
+    Ok(())
+}
+
+fn main() {
+    let Some(prog_path) = env::args().nth(1) else {
+        eprintln!("usage: intdbg <prog.txt> [input...]");
+        process::exit(2);
+    };
+    let prog = Program::load(&prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    let input: Vec<i64> = env::args().skip(2).map(|v| parse_val(&v)).collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2);
+        });
+    let mut dbg = Debugger::new(Computer::new(&prog).with_input(input));
+    print_next(&dbg);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(intdbg) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&cmd, args)) = words.split_first() else {
+            continue;
+        };
+        if cmd == "quit" || cmd == "q" {
+            break;
+        }
+        if let Err(e) = exec(&mut dbg, cmd, args) {
+            println!("error: {}", e);
+        }
+        if !dbg.comp.outs.is_empty() {
+            let outs: Vec<String> = dbg.comp.outs.drain(..).map(|v| v.to_string()).collect();
+            println!("out: {}", outs.join(" "));
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use std::{env, process};
+
+use intcode::{decompile::decompile, program::Program};
+
+const USAGE: &str = "usage: intdecomp <prog.txt>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let prog_path = match &args[..] {
+        [path] => path,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let prog = Program::load(prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    print!("{}", decompile(&prog.code));
+}
@@ -0,0 +1,15 @@
+use std::{env, process};
+
+use intcode::{disasm::disassemble, program::Program};
+
+fn main() {
+    let Some(prog_path) = env::args().nth(1) else {
+        eprintln!("usage: intdis <prog.txt>");
+        process::exit(2);
+    };
+    let prog = Program::load(&prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    print!("{}", disassemble(&prog.code));
+}
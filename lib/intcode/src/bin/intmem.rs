@@ -0,0 +1,155 @@
+use std::{env, io::{self, BufRead, Write}, process};
+
+use intcode::{
+    int::{Computer, ComputerState},
+    program::Program,
+    scan::{diff, find, Filter, Scan},
+    snapshot::Snapshot,
+};
+
+const USAGE: &str = "\
+usage: intmem diff <a.snap> <b.snap>
+       intmem find <snap> <val>
+       intmem scan <prog.txt> [input...]";
+
+const HELP: &str = "\
+commands:
+  run                     run until the program wants input or halts
+  input <val>...          queue input values
+  set <addr> <val>        write memory, e.g. to insert coins
+  eq <val>                keep addresses holding a value
+  changed / unchanged     keep addresses that changed or not since the last filter
+  inc / dec               keep addresses that went up or down
+  by <delta>              keep addresses that changed by exactly delta
+  list [n]                print up to n candidates, default 20
+  reset                   start over with every address
+  quit";
+
+fn parse_val(s: &str) -> Result<i64, String> {
+    s.parse().map_err(|_| format!("bad value \"{}\"", s))
+}
+
+fn load_snapshot(path: &str) -> Snapshot {
+    Snapshot::load(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    })
+}
+
+fn parse_filter(cmd: &str, args: &[&str]) -> Result<Option<Filter>, String> {
+    let arg = || args.first().ok_or(format!("usage: {} <val>", cmd)).and_then(|v| parse_val(v));
+    Ok(Some(match cmd {
+        "eq" => Filter::Equals(arg()?),
+        "changed" => Filter::Changed,
+        "unchanged" => Filter::Unchanged,
+        "inc" => Filter::Increased,
+        "dec" => Filter::Decreased,
+        "by" => Filter::ChangedBy(arg()?),
+        _ => return Ok(None),
+    }))
+}
+
+fn exec(comp: &mut Computer, scan: &mut Scan, cmd: &str, args: &[&str]) -> Result<(), String> {
+    if let Some(filter) = parse_filter(cmd, args)? {
+        println!("{} candidates", scan.keep(&comp.mem, filter));
+        return Ok(());
+    }
+    match cmd {
+        "help" | "h" => println!("{}", HELP),
+        "run" | "r" => {
+            let state = loop {
+                match comp.run().map_err(|e| e.to_string())? {
+                    ComputerState::WriteInt => {}
+                    state => break state,
+                }
+            };
+            let outs: Vec<String> = comp.outs.drain(..).map(|v| v.to_string()).collect();
+            println!("out: {}", outs.join(" "));
+            println!("{:?}", state);
+        }
+        "input" | "i" => {
+            let vals = args.iter().map(|v| parse_val(v)).collect::<Result<Vec<_>, _>>()?;
+            comp.ins.extend(vals);
+        }
+        "set" => match args {
+            [addr, val] => {
+                let addr = addr.parse().map_err(|_| format!("bad address \"{}\"", addr))?;
+                comp.mem.set(addr, parse_val(val)?)
+                    .map_err(|e| format!("address {} over memory limit {}", e.addr, e.limit))?;
+            }
+            _ => return Err("usage: set <addr> <val>".to_owned()),
+        },
+        "list" | "l" => {
+            let n = args.first().map(|n| parse_val(n)).transpose()?.unwrap_or(20);
+            for (addr, _) in scan.candidates().take(n.max(0) as usize) {
+                println!("{:>6}: {}", addr, comp.mem.get(addr));
+            }
+            println!("{} candidates", scan.len());
+        }
+        "reset" => {
+            *scan = Scan::new(&comp.mem);
+            println!("{} candidates", scan.len());
+        }
+        _ => return Err(format!("unknown command \"{}\", try help", cmd)),
+    }
+    Ok(())
+}
+
+fn interactive(prog_path: &str, input: &[String]) {
+    let prog = Program::load(prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    let input: Vec<i64> = input.iter().map(|v| parse_val(v)).collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2);
+        });
+    let mut comp = Computer::new(&prog).with_input(input);
+    let mut scan = Scan::new(&comp.mem);
+
+    let stdin = io::stdin();
+    loop {
+        print!("(intmem) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&cmd, args)) = words.split_first() else {
+            continue;
+        };
+        if cmd == "quit" || cmd == "q" {
+            break;
+        }
+        if let Err(e) = exec(&mut comp, &mut scan, cmd, args) {
+            println!("error: {}", e);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match &args[..] {
+        [cmd, a, b] if cmd == "diff" => {
+            for change in diff(&load_snapshot(a).mem, &load_snapshot(b).mem) {
+                println!("{}", change);
+            }
+        }
+        [cmd, snap, val] if cmd == "find" => {
+            let val = parse_val(val).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                process::exit(2);
+            });
+            for addr in find(&load_snapshot(snap).mem, val) {
+                println!("{}", addr);
+            }
+        }
+        [cmd, prog, input @ ..] if cmd == "scan" => interactive(prog, input),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+}
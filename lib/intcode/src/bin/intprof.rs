@@ -0,0 +1,60 @@
+use std::{env, fs::File, io::BufWriter, process};
+
+use intcode::{
+    int::{Computer, ComputerState},
+    program::Program,
+    trace::{JsonLinesTracer, Profile},
+};
+
+const USAGE: &str = "usage: intprof <prog.txt> [--trace <out.jsonl>] [input...]";
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let Some(prog_path) = args.next() else {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    };
+    let mut trace_path = None;
+    let mut input = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "--trace" {
+            trace_path = args.next();
+        } else if let Ok(val) = arg.parse::<i64>() {
+            input.push(val);
+        } else {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+
+    let prog = Program::load(&prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    let mut comp = Computer::new(&prog).with_input(input);
+    let json = trace_path.as_ref().map(|path| {
+        let file = File::create(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            process::exit(1);
+        });
+        JsonLinesTracer::new(BufWriter::new(file))
+    });
+    let mut tracer = (Profile::new(), json);
+    let mut res = comp.run_traced(&mut tracer);
+    while res == Ok(ComputerState::WriteInt) {
+        res = comp.run_traced(&mut tracer);
+    }
+    let (profile, json) = tracer;
+    if let Some(Err(e)) = json.map(|json| json.finish()) {
+        eprintln!("{}: {}", trace_path.unwrap(), e);
+    }
+
+    match res {
+        Ok(ComputerState::ReadInt) => println!("stopped waiting for input"),
+        Ok(_) => println!("halted"),
+        Err(e) => println!("error: {}", e),
+    }
+    let outs: Vec<String> = comp.outs.iter().map(|v| v.to_string()).collect();
+    println!("outputs: {}", outs.join(","));
+    print!("{}", profile);
+}
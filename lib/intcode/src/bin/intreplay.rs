@@ -0,0 +1,28 @@
+use std::{env, process};
+
+use intcode::{program::Program, replay::Session};
+
+const USAGE: &str = "usage: intreplay <prog.txt> <session.txt>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [prog_path, session_path] = &args[..] else {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    };
+    let prog = Program::load(prog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", prog_path, e);
+        process::exit(1);
+    });
+    let session = Session::load(session_path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", session_path, e);
+        process::exit(1);
+    });
+    match session.replay(&prog) {
+        Ok(()) => println!("ok, {} events replayed", session.events.len()),
+        Err(e) => {
+            println!("diverged: {}", e);
+            process::exit(1);
+        }
+    }
+}
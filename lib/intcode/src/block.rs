@@ -0,0 +1,494 @@
+use std::sync::Arc;
+
+use crate::{
+    int::{decode, Computer, ComputerState, OpCode, OpParamMode, VmError},
+    mem::Memory,
+};
+
+// longest straight-line run compiled into one block
+pub(crate) const MAX_BLOCK_OPS: usize = 64;
+const MAX_BLOCK_WORDS: usize = 4 * MAX_BLOCK_OPS + 4;
+
+/// How `Computer::run` executes. `step` and the traced runs always interpret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    #[default]
+    Interpreter,
+    /// Pre-decodes straight-line code into blocks and reuses them on every
+    /// visit. Writes through the machine invalidate the blocks they hit,
+    /// editing `mem` by hand between runs needs `Computer::with_engine` or
+    /// a snapshot restore to revalidate.
+    Blocks,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Src {
+    Imm(i64),
+    Pos(i64),
+    Rel(i64),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Dst {
+    Pos(i64),
+    Rel(i64),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Add(Src, Src, Dst),
+    Mul(Src, Src, Dst),
+    Less(Src, Src, Dst),
+    Equals(Src, Src, Dst),
+    Arb(Src),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Term {
+    /// Jumps when the condition being non-zero matches the flag.
+    JumpIf(bool, Src, Src),
+    Read(Dst),
+    Write(Src),
+    Halt,
+    /// Left to the interpreter: bad instructions, immediate writes and
+    /// blocks cut at `MAX_BLOCK_OPS`.
+    Step,
+}
+
+/// Straight-line run of instructions ending in a jump, I/O or halt.
+#[derive(Debug)]
+struct Block {
+    start: usize,
+    /// Code the block was compiled from, `start..start + words.len()`.
+    words: Box<[i64]>,
+    /// Ops with the address of their instruction.
+    ops: Box<[(usize, Op)]>,
+    term_eip: usize,
+    term: Term,
+}
+
+impl Block {
+    fn compile(start: usize, mem: &Memory) -> Block {
+        let mut ops = vec![];
+        let mut eip = start;
+        let term = loop {
+            if ops.len() == MAX_BLOCK_OPS {
+                break Term::Step;
+            }
+            let Ok(instr) = decode(eip, mem.get(eip)) else { break Term::Step };
+            let size = instr.code.size();
+            if eip + size > mem.limit() {
+                break Term::Step;
+            }
+            let word = |i: usize| mem.get(eip + 1 + i);
+            let src = |i: usize| match instr.modes[i] {
+                OpParamMode::Position => Src::Pos(word(i)),
+                OpParamMode::Immediate => Src::Imm(word(i)),
+                OpParamMode::Relative => Src::Rel(word(i)),
+            };
+            let dst = |i: usize| match instr.modes[i] {
+                OpParamMode::Position => Some(Dst::Pos(word(i))),
+                OpParamMode::Immediate => None,
+                OpParamMode::Relative => Some(Dst::Rel(word(i))),
+            };
+            let op = match instr.code {
+                OpCode::Add | OpCode::Mul | OpCode::CmpLess | OpCode::CmpEquals => {
+                    let Some(d) = dst(2) else { break Term::Step };
+                    match instr.code {
+                        OpCode::Add => Op::Add(src(0), src(1), d),
+                        OpCode::Mul => Op::Mul(src(0), src(1), d),
+                        OpCode::CmpLess => Op::Less(src(0), src(1), d),
+                        _ => Op::Equals(src(0), src(1), d),
+                    }
+                }
+                OpCode::SpAdd => Op::Arb(src(0)),
+                OpCode::Read => break dst(0).map_or(Term::Step, Term::Read),
+                OpCode::Write => break Term::Write(src(0)),
+                OpCode::JumpIfTrue => break Term::JumpIf(true, src(0), src(1)),
+                OpCode::JumpIfFalse => break Term::JumpIf(false, src(0), src(1)),
+                OpCode::Halt => break Term::Halt,
+            };
+            ops.push((eip, op));
+            eip += size;
+        };
+        let end = match (term, decode(eip, mem.get(eip))) {
+            (Term::Step, _) | (_, Err(_)) => eip,
+            (_, Ok(instr)) => eip + instr.code.size(),
+        };
+        let words = (start..end).map(|addr| mem.get(addr)).collect();
+        Block { start, words, ops: ops.into(), term_eip: eip, term }
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.words.len()
+    }
+
+    fn matches(&self, mem: &Memory) -> bool {
+        self.words.iter().enumerate().all(|(i, &w)| mem.get(self.start + i) == w)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Blocks {
+    by_start: Vec<Option<Arc<Block>>>,
+    len: usize,
+    // number of blocks compiled from each address
+    cover: Vec<u32>,
+}
+
+impl Blocks {
+    fn insert(&mut self, block: Arc<Block>) {
+        self.add_cover(&block, 1);
+        let start = block.start;
+        if self.by_start.len() <= start {
+            self.by_start.resize(start + 1, None);
+        }
+        self.by_start[start] = Some(block);
+        self.len += 1;
+    }
+
+    fn remove(&mut self, start: usize) {
+        if let Some(block) = self.by_start[start].take() {
+            self.add_cover(&block, -1);
+            self.len -= 1;
+        }
+    }
+
+    fn add_cover(&mut self, block: &Block, n: i32) {
+        if self.cover.len() < block.end() {
+            self.cover.resize(block.end(), 0);
+        }
+        for c in self.cover[block.start..block.end()].iter_mut() {
+            *c = c.checked_add_signed(n).unwrap();
+        }
+    }
+}
+
+/// Compiled blocks of one machine by start address. Clones share the
+/// blocks until one side changes them, so a cache warmed by one run can
+/// seed the next fresh machine for free.
+#[derive(Debug, Clone, Default)]
+pub struct BlockCache {
+    inner: Arc<Blocks>,
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache::default()
+    }
+
+    /// Number of compiled blocks.
+    pub fn len(&self) -> usize {
+        self.inner.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.len == 0
+    }
+
+    fn get(&mut self, start: usize, mem: &Memory) -> Arc<Block> {
+        if let Some(Some(block)) = self.inner.by_start.get(start) {
+            return Arc::clone(block);
+        }
+        let block = Arc::new(Block::compile(start, mem));
+        Arc::make_mut(&mut self.inner).insert(Arc::clone(&block));
+        block
+    }
+
+    /// Drops every block compiled from `addr`. Returns whether there was one.
+    pub(crate) fn invalidate(&mut self, addr: usize) -> bool {
+        if self.inner.cover.get(addr).is_none_or(|&c| c == 0) {
+            return false;
+        }
+        let blocks = Arc::make_mut(&mut self.inner);
+        let first = addr.saturating_sub(MAX_BLOCK_WORDS);
+        for start in first..=addr.min(blocks.by_start.len() - 1) {
+            if blocks.by_start[start].as_ref().is_some_and(|b| b.end() > addr) {
+                blocks.remove(start);
+            }
+        }
+        true
+    }
+
+    fn merge(&mut self, other: &BlockCache, mem: &Memory) {
+        if Arc::ptr_eq(&self.inner, &other.inner) {
+            return;
+        }
+        let new: Vec<Arc<Block>> = other.inner.by_start.iter()
+            .flatten()
+            .filter(|b| !matches!(self.inner.by_start.get(b.start), Some(Some(_))) && b.matches(mem))
+            .cloned()
+            .collect();
+        if new.is_empty() {
+            return;
+        }
+        let blocks = Arc::make_mut(&mut self.inner);
+        for block in new {
+            blocks.insert(block);
+        }
+    }
+
+    /// Drops the blocks whose code no longer matches `mem`.
+    pub(crate) fn revalidate(&mut self, mem: &Memory) {
+        let stale: Vec<usize> = self.inner.by_start.iter()
+            .flatten()
+            .filter(|b| !b.matches(mem))
+            .map(|b| b.start)
+            .collect();
+        if stale.is_empty() {
+            return;
+        }
+        let blocks = Arc::make_mut(&mut self.inner);
+        for start in stale {
+            blocks.remove(start);
+        }
+    }
+}
+
+impl Computer {
+    /// Switches engines, keeping the block cache if there is one.
+    pub fn with_engine(mut self, engine: Engine) -> Computer {
+        match engine {
+            Engine::Interpreter => {
+                self.blocks = None;
+                self
+            }
+            Engine::Blocks => {
+                let cache = self.blocks.take().unwrap_or_default();
+                self.with_block_cache(cache)
+            }
+        }
+    }
+
+    /// Runs with the block engine starting from `cache`, dropping blocks
+    /// compiled from code that differs in this machine's memory.
+    pub fn with_block_cache(mut self, mut cache: BlockCache) -> Computer {
+        cache.revalidate(&self.mem);
+        self.blocks = Some(cache);
+        self
+    }
+
+    /// Adds the blocks `other` compiled that match this machine's code,
+    /// so forks of a template machine can warm it up. Does nothing unless
+    /// both run on the block engine.
+    pub fn adopt_blocks(&mut self, other: &Computer) {
+        if let (Some(ours), Some(theirs)) = (&mut self.blocks, &other.blocks) {
+            ours.merge(theirs, &self.mem);
+        }
+    }
+
+    pub fn engine(&self) -> Engine {
+        if self.blocks.is_some() { Engine::Blocks } else { Engine::Interpreter }
+    }
+
+    pub fn block_cache(&self) -> Option<&BlockCache> {
+        self.blocks.as_ref()
+    }
+
+    fn src(&self, src: Src) -> Result<i64, VmError> {
+        match src {
+            Src::Imm(val) => Ok(val),
+            Src::Pos(addr) => self.load(addr),
+            Src::Rel(off) => self.load((self.esp as i64).wrapping_add(off)),
+        }
+    }
+
+    // stores like the interpreter, true if the write hit compiled code
+    fn dst(&mut self, dst: Dst, val: i64) -> Result<bool, VmError> {
+        let addr = match dst {
+            Dst::Pos(addr) => addr,
+            Dst::Rel(off) => (self.esp as i64).wrapping_add(off),
+        };
+        self.store_code(addr, val)
+    }
+
+    fn exec_block(&mut self, block: &Block) -> Result<Option<ComputerState>, VmError> {
+        for &(eip, op) in block.ops.iter() {
+            self.eip = eip;
+            let (val, dst) = match op {
+                Op::Add(a, b, d) => (self.src(a)?.wrapping_add(self.src(b)?), d),
+                Op::Mul(a, b, d) => (self.src(a)?.wrapping_mul(self.src(b)?), d),
+                Op::Less(a, b, d) => (i64::from(self.src(a)? < self.src(b)?), d),
+                Op::Equals(a, b, d) => (i64::from(self.src(a)? == self.src(b)?), d),
+                Op::Arb(a) => {
+                    let esp = (self.esp as i64).wrapping_add(self.src(a)?);
+                    self.esp = usize::try_from(esp)
+                        .map_err(|_| VmError::NegativeAddress { eip, addr: esp })?;
+                    continue;
+                }
+            };
+            if self.dst(dst, val)? {
+                // the rest of the block may be stale
+                self.eip = eip + 4;
+                return Ok(None);
+            }
+        }
+        let eip = block.term_eip;
+        self.eip = eip;
+        match block.term {
+            Term::JumpIf(when, cond, target) => {
+                let (cond, target) = (self.src(cond)?, self.src(target)?);
+                if (cond != 0) == when {
+                    self.eip = usize::try_from(target)
+                        .map_err(|_| VmError::NegativeAddress { eip, addr: target })?;
+                } else {
+                    self.eip = eip + 3;
+                }
+            }
+            Term::Read(dst) => {
+                let Some(val) = self.ins.pop_front() else {
+                    return Ok(Some(ComputerState::ReadInt));
+                };
+                self.dst(dst, val)?;
+                self.eip = eip + 2;
+            }
+            Term::Write(src) => {
+                let val = self.src(src)?;
+                self.outs.push_back(val);
+                self.eip = eip + 2;
+                return Ok(Some(ComputerState::WriteInt));
+            }
+            Term::Halt => return Ok(Some(ComputerState::Halted)),
+            Term::Step => return self.step(),
+        }
+        Ok(None)
+    }
+
+    // runs the block at eip, past anything ever written there's nothing to
+    // compile and the interpreter reports the bad opcode
+    fn run_block(&mut self) -> Result<Option<ComputerState>, VmError> {
+        if self.eip >= self.mem.len() {
+            return self.step();
+        }
+        let block = self.blocks.as_mut().unwrap().get(self.eip, &self.mem);
+        self.exec_block(&block)
+    }
+
+    pub(crate) fn run_blocks(&mut self) -> Result<ComputerState, VmError> {
+        loop {
+            if let Some(state) = self.run_block()? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// `run_blocks` stopping with `BudgetExhausted` after `max_blocks`
+    /// blocks. Every block runs at least one instruction, so this stops
+    /// no earlier than a budget of as many steps would.
+    #[cfg(test)]
+    pub(crate) fn run_blocks_for(&mut self, max_blocks: u64) -> Result<ComputerState, VmError> {
+        for _ in 0..max_blocks {
+            if let Some(state) = self.run_block()? {
+                return Ok(state);
+            }
+        }
+        Ok(ComputerState::BudgetExhausted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{program::Program, test_util::boost};
+
+    // runs to the end feeding `input`, returns everything written and the final state
+    fn outputs(mut comp: Computer, input: &[i64]) -> (Vec<i64>, Result<ComputerState, VmError>) {
+        comp.ins.extend(input);
+        let mut res = comp.run();
+        while res == Ok(ComputerState::WriteInt) {
+            res = comp.run();
+        }
+        (comp.outs.into(), res)
+    }
+
+    fn same_as_interpreter(prog: &Program, input: &[i64]) -> Vec<i64> {
+        let interpreted = outputs(Computer::new(prog), input);
+        let compiled = outputs(Computer::new(prog).with_engine(Engine::Blocks), input);
+        assert_eq!(compiled, interpreted);
+        compiled.0
+    }
+
+    #[test]
+    fn test_boost() {
+        let boost = boost();
+        assert_eq!(same_as_interpreter(&boost, &[2]), [51754]);
+        assert_eq!(same_as_interpreter(&boost, &[1]).len(), 1);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // the first add bumps the immediate operand of the second
+        let prog = Program::from(vec![101, 1, 6, 6, 1101, 100, 0, 20, 4, 20, 1007, 6, 3, 21, 1005, 21, 0, 99]);
+        assert_eq!(same_as_interpreter(&prog, &[]), [101, 102, 103]);
+
+        // reads patch an operand of a block that already ran
+        let prog = Program::from(vec![3, 4, 1101, 5, 0, 12, 4, 12, 1105, 1, 0, 99, 0]);
+        assert_eq!(same_as_interpreter(&prog, &[1, 2]), [6, 7]);
+
+        // a block cut at MAX_BLOCK_OPS leaves the next instruction to the
+        // interpreter, its write must still drop the block it patches
+        let (r, s, f, hlt) = (273, 274, 275, 272);
+        let mut code = vec![1101, 7, 0, r];
+        for _ in 1..MAX_BLOCK_OPS {
+            code.extend([1101, 0, 0, s]);
+        }
+        code.extend([1101, 9, 0, 1, 4, r, 1005, f, hlt, 1101, 1, 0, f, 1105, 1, 0, 99, 0, 0, 0]);
+        assert_eq!(code.len(), 276);
+        assert_eq!(same_as_interpreter(&Program::from(code), &[]), [7, 9]);
+
+        let prog = Program::from(vec![1002, 4, 3, 4, 33]);
+        let mut comp = Computer::new(&prog).with_engine(Engine::Blocks);
+        assert_eq!(comp.run(), Ok(ComputerState::Halted));
+    }
+
+    #[test]
+    fn test_errors() {
+        for code in [
+            vec![1101, 1, 1, -1],
+            vec![1101, 1, 1, 5, 9, -3, 99],
+            vec![21101, 1, 1, -1, 99],
+            vec![1101, 1, 1, 7, 11101, 1, 1, 0],
+            vec![1101, 1, 1, 7, 42],
+        ] {
+            same_as_interpreter(&Program::from(code), &[]);
+        }
+        let prog = Program::from(vec![1101, 1, 1, 100, 99]);
+        let mut comp = Computer::new(&prog).with_mem_limit(100).with_engine(Engine::Blocks);
+        assert_eq!(comp.run(), Err(VmError::OutOfMemory { eip: 0, addr: 100 }));
+    }
+
+    #[test]
+    fn test_shared_cache() {
+        let prog = Program::from(vec![1101, 1, 2, 8, 4, 8, 99, 0, 0]);
+        let mut comp = Computer::new(&prog).with_engine(Engine::Blocks);
+        comp.run().unwrap();
+        let cache = comp.block_cache().unwrap().clone();
+        assert_eq!(cache.len(), 1);
+        let mut comp = Computer::new(&prog).with_block_cache(cache.clone());
+        assert_eq!(comp.block_cache().unwrap().len(), 1);
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.outs, [3]);
+
+        // the patched operand is part of the cached block
+        let mut comp = Computer::new(&prog).with_patch(1, 10).with_block_cache(cache.clone());
+        assert!(comp.block_cache().unwrap().is_empty());
+        comp.run().unwrap();
+        assert_eq!(comp.outs, [12]);
+        assert_eq!(comp.with_engine(Engine::Interpreter).engine(), Engine::Interpreter);
+    }
+
+    #[test]
+    fn test_adopt_blocks() {
+        let prog = Program::from(vec![3, 15, 1008, 15, 1, 16, 1005, 16, 12, 104, 0, 99, 104, 1, 99, 0, 0]);
+        let mut template = Computer::new(&prog).with_engine(Engine::Blocks);
+        for input in [0, 1, 0] {
+            let mut comp = template.clone().with_input([input]);
+            assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+            assert_eq!(comp.outs, [input]);
+            template.adopt_blocks(&comp);
+        }
+        // the read, the compare and both outputs
+        assert_eq!(template.block_cache().unwrap().len(), 4);
+        assert_eq!(template.mem[15], 0);
+    }
+}
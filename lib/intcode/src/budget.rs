@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use crate::{
+    int::{Computer, ComputerState, VmError},
+    mem::Memory,
+    trace::Tracer,
+};
+
+/// Instructions a machine may still execute, shared by as many runs as
+/// needed, e.g. a whole exploration of an unknown program.
+///
+/// With loop detection the machine state (eip, rb, pending input and
+/// memory) is checked every `interval` steps. Seeing one again before any
+/// I/O means the program can never get out, the run fails with
+/// `VmError::InfiniteLoop`. Loops shorter than the interval are caught
+/// too, but checking more often costs a memory hash each time and keeps
+/// a copy-on-write fork of memory until the next I/O.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    max_steps: u64,
+    used: u64,
+    interval: Option<u64>,
+    // memories seen per (eip, rb, pending input, memory hash), hash
+    // collisions are told apart by comparing contents
+    seen: HashMap<(usize, usize, usize, u64), Vec<Memory>>,
+}
+
+impl Budget {
+    pub fn new(max_steps: u64) -> Budget {
+        Budget { max_steps, used: 0, interval: None, seen: HashMap::new() }
+    }
+
+    pub fn with_loop_detection(mut self, interval: u64) -> Budget {
+        self.interval = Some(interval.max(1));
+        self
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.max_steps - self.used
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.used >= self.max_steps
+    }
+
+    /// Allows `steps` more instructions.
+    pub fn extend(&mut self, steps: u64) {
+        self.max_steps = self.max_steps.saturating_add(steps);
+    }
+
+    // false if the machine is somewhere it has already been
+    fn check(&mut self, comp: &Computer) -> bool {
+        match self.interval {
+            Some(interval) if self.used.is_multiple_of(interval) => {
+                let key = (comp.eip, comp.esp, comp.ins.len(), comp.mem.content_hash());
+                let mems = self.seen.entry(key).or_default();
+                if mems.iter().any(|mem| mem.same_contents(&comp.mem)) {
+                    return false;
+                }
+                mems.push(comp.mem.clone());
+                true
+            }
+            _ => true,
+        }
+    }
+}
+
+impl Computer {
+    /// `run` that stops with `BudgetExhausted` after `max_steps` instructions.
+    pub fn run_with_budget(&mut self, max_steps: u64) -> Result<ComputerState, VmError> {
+        self.run_budgeted(&mut Budget::new(max_steps))
+    }
+
+    /// `run` that spends `budget` and stops with `BudgetExhausted` once it's
+    /// used up. Always interprets, the block engine can't count steps.
+    pub fn run_budgeted(&mut self, budget: &mut Budget) -> Result<ComputerState, VmError> {
+        self.run_budgeted_with(budget, Computer::step)
+    }
+
+    /// `run_budgeted` reporting every executed instruction to `tracer`.
+    pub fn run_budgeted_traced(&mut self, budget: &mut Budget, tracer: &mut impl Tracer) -> Result<ComputerState, VmError> {
+        self.run_budgeted_with(budget, |comp| comp.step_traced(tracer))
+    }
+
+    fn run_budgeted_with(
+        &mut self,
+        budget: &mut Budget,
+        mut step: impl FnMut(&mut Computer) -> Result<Option<ComputerState>, VmError>,
+    ) -> Result<ComputerState, VmError> {
+        // states seen before the last I/O may legitimately come back
+        budget.seen.clear();
+        loop {
+            if budget.is_exhausted() {
+                return Ok(ComputerState::BudgetExhausted);
+            }
+            if !budget.check(self) {
+                return Err(VmError::InfiniteLoop { eip: self.eip });
+            }
+            let state = step(self)?;
+            if state != Some(ComputerState::ReadInt) {
+                budget.used += 1;
+            }
+            if let Some(state) = state {
+                return Ok(state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{assembled, COUNTDOWN_INPUT},
+        trace::Profile,
+    };
+
+    #[test]
+    fn test_budget() {
+        let mut comp = assembled(COUNTDOWN_INPUT).with_input([3]);
+        assert_eq!(comp.run_with_budget(1), Ok(ComputerState::BudgetExhausted));
+        assert_eq!(comp.run_with_budget(1), Ok(ComputerState::WriteInt));
+
+        let mut budget = Budget::new(5);
+        assert_eq!(comp.run_budgeted(&mut budget), Ok(ComputerState::WriteInt));
+        assert_eq!(budget.used(), 3);
+        assert_eq!(comp.run_budgeted(&mut budget), Ok(ComputerState::BudgetExhausted));
+        budget.extend(10);
+        assert_eq!(comp.run_budgeted(&mut budget), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.run_budgeted(&mut budget), Ok(ComputerState::Halted));
+        assert_eq!(Vec::from(comp.outs), [3, 2, 1]);
+
+        budget.extend(u64::MAX);
+        assert!(!budget.is_exhausted());
+        assert_eq!(budget.remaining(), u64::MAX - budget.used());
+    }
+
+    #[test]
+    fn test_waiting_for_input_is_free() {
+        let mut comp = assembled(COUNTDOWN_INPUT);
+        let mut budget = Budget::new(1);
+        assert_eq!(comp.run_budgeted(&mut budget), Ok(ComputerState::ReadInt));
+        assert_eq!(budget.used(), 0);
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        // spins on a flag nothing ever sets
+        let mut comp = assembled("
+                out #1
+            wait: jf [flag], #wait
+                hlt
+            flag: .data 0
+        ");
+        let mut budget = Budget::new(1_000_000).with_loop_detection(7);
+        assert_eq!(comp.run_budgeted(&mut budget), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.run_budgeted(&mut budget), Err(VmError::InfiniteLoop { eip: 2 }));
+        assert!(budget.used() < 20);
+
+        // a long count isn't a loop
+        let mut comp = assembled(COUNTDOWN_INPUT).with_input([1000]);
+        let mut budget = Budget::new(1_000_000).with_loop_detection(1);
+        let mut profile = Profile::new();
+        while comp.run_budgeted_traced(&mut budget, &mut profile) == Ok(ComputerState::WriteInt) {}
+        assert_eq!(comp.outs.len(), 1000);
+        assert_eq!(budget.used(), profile.steps);
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    disasm::{code_refs, decode_at, disassemble, falls_through, find_code, label_name, may_jump, Item, Listing},
+    int::{OpCode, OpParamMode},
+};
+
+/// How control leaves a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// Runs into the next block.
+    Next,
+    /// Jump to an immediate target, `cond` if it may fall through instead.
+    Jump { target: usize, cond: bool },
+    /// Pushes `ret` to `rb+0` and jumps to the function at `target`.
+    Call { target: usize, ret: usize },
+    /// Jumps to an address on the stack, `rb+N`.
+    Return { cond: bool },
+    /// Jumps to an address read from memory.
+    Indirect { cond: bool },
+    Halt,
+    /// Runs into data or a jump target that isn't code.
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edge {
+    Next,
+    Taken,
+    Call,
+    /// From a call to where the callee returns to.
+    AfterCall,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// One past the last word of the last instruction.
+    pub end: usize,
+    /// Instruction addresses.
+    pub instrs: Vec<usize>,
+    pub exit: Exit,
+    pub succs: Vec<(Edge, usize)>,
+}
+
+/// Blocks reachable from `entry` without following calls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub entry: usize,
+    pub blocks: BTreeSet<usize>,
+    /// Entries of the functions it calls.
+    pub calls: BTreeSet<usize>,
+}
+
+/// Control-flow graph of the code reachable from address 0, with functions
+/// recovered from the usual calling convention: push the return address
+/// to `rb+0`, jump, and `jt #1, rb+0` back.
+#[derive(Debug, Clone)]
+pub struct Cfg {
+    pub listing: Listing,
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub functions: BTreeMap<usize, Function>,
+}
+
+fn is_jump(code: OpCode) -> bool {
+    matches!(code, OpCode::JumpIfTrue | OpCode::JumpIfFalse)
+}
+
+// exit of a block ending in the jump at `addr`, `prev` the instruction before it
+fn jump_exit(code: &[i64], starts: &BTreeSet<usize>, addr: usize, prev: Option<usize>) -> Exit {
+    let (instr, args) = decode_at(code, addr).unwrap();
+    let next = addr + instr.code.size();
+    let cond = falls_through(&instr, args);
+    if !may_jump(&instr, args) {
+        return if starts.contains(&next) { Exit::Next } else { Exit::End };
+    }
+    match instr.modes[1] {
+        OpParamMode::Relative => return Exit::Return { cond },
+        OpParamMode::Position => return Exit::Indirect { cond },
+        OpParamMode::Immediate => {}
+    }
+    let Some(target) = usize::try_from(args[1]).ok().filter(|t| starts.contains(t)) else {
+        return Exit::End;
+    };
+    let pushes_next = prev.is_some_and(|prev| {
+        let (instr, args) = decode_at(code, prev).unwrap();
+        // the only code refs outside of jumps are stores to rb+0
+        code_refs(&instr, args).iter().any(|&(_, val)| val == next as i64)
+    });
+    if !cond && pushes_next && starts.contains(&next) {
+        Exit::Call { target, ret: next }
+    } else {
+        Exit::Jump { target, cond }
+    }
+}
+
+impl Cfg {
+    pub fn new(code: &[i64]) -> Cfg {
+        let starts = find_code(code);
+        let mut leaders = BTreeSet::new();
+        if starts.contains(&0) {
+            leaders.insert(0);
+        }
+        for &addr in starts.iter() {
+            let (instr, args) = decode_at(code, addr).unwrap();
+            for (_, target) in code_refs(&instr, args) {
+                if let Some(target) = usize::try_from(target).ok().filter(|t| starts.contains(t)) {
+                    leaders.insert(target);
+                }
+            }
+            let next = addr + instr.code.size();
+            if (is_jump(instr.code) || instr.code == OpCode::Halt) && starts.contains(&next) {
+                leaders.insert(next);
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in leaders.iter() {
+            let mut instrs = vec![];
+            let mut addr = start;
+            let exit = loop {
+                let (instr, _) = decode_at(code, addr).unwrap();
+                instrs.push(addr);
+                let next = addr + instr.code.size();
+                if instr.code == OpCode::Halt {
+                    break Exit::Halt;
+                }
+                if is_jump(instr.code) {
+                    let prev = instrs.len().checked_sub(2).map(|i| instrs[i]);
+                    break jump_exit(code, &starts, addr, prev);
+                }
+                if !starts.contains(&next) {
+                    break Exit::End;
+                }
+                if leaders.contains(&next) {
+                    break Exit::Next;
+                }
+                addr = next;
+            };
+            let last = *instrs.last().unwrap();
+            let end = last + decode_at(code, last).unwrap().0.code.size();
+            let succs = match exit {
+                Exit::Next => vec![(Edge::Next, end)],
+                Exit::Jump { target, cond: true } => vec![(Edge::Taken, target), (Edge::Next, end)],
+                Exit::Jump { target, cond: false } => vec![(Edge::Taken, target)],
+                Exit::Call { target, ret } => vec![(Edge::Call, target), (Edge::AfterCall, ret)],
+                Exit::Return { cond: true } | Exit::Indirect { cond: true } => vec![(Edge::Next, end)],
+                _ => vec![],
+            };
+            let succs = succs.into_iter().filter(|(_, to)| leaders.contains(to)).collect();
+            blocks.insert(start, BasicBlock { start, end, instrs, exit, succs });
+        }
+
+        let mut entries: BTreeSet<usize> = blocks.values()
+            .filter_map(|b| match b.exit {
+                Exit::Call { target, .. } => Some(target),
+                _ => None,
+            })
+            .collect();
+        if blocks.contains_key(&0) {
+            entries.insert(0);
+        }
+        let functions = entries.into_iter()
+            .map(|entry| (entry, Cfg::function(&blocks, entry)))
+            .collect();
+        Cfg { listing: disassemble(code), blocks, functions }
+    }
+
+    fn function(blocks: &BTreeMap<usize, BasicBlock>, entry: usize) -> Function {
+        let mut body = BTreeSet::new();
+        let mut calls = BTreeSet::new();
+        let mut stack = vec![entry];
+        while let Some(start) = stack.pop() {
+            if !body.insert(start) {
+                continue;
+            }
+            for &(edge, to) in blocks[&start].succs.iter() {
+                if edge == Edge::Call {
+                    calls.insert(to);
+                } else {
+                    stack.push(to);
+                }
+            }
+        }
+        Function { entry, blocks: body, calls }
+    }
+
+    /// Function whose cluster a block is drawn in, the first one containing it.
+    pub fn owner(&self, block: usize) -> Option<usize> {
+        self.functions.values().find(|f| f.blocks.contains(&block)).map(|f| f.entry)
+    }
+
+    fn block_label(&self, block: &BasicBlock) -> String {
+        let mut label = format!("{}:\\l", label_name(block.start));
+        for item in self.listing.items.iter() {
+            if let Item::Instr { addr, .. } = item {
+                if block.instrs.contains(addr) {
+                    label.push_str(&format!("    {}\\l", self.listing.item_text(item)));
+                }
+            }
+        }
+        label.replace('"', "\\\"")
+    }
+
+    /// Graphviz source with one cluster per function.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut owned: BTreeMap<Option<usize>, Vec<&BasicBlock>> = BTreeMap::new();
+        for block in self.blocks.values() {
+            owned.entry(self.owner(block.start)).or_default().push(block);
+        }
+        for (owner, blocks) in owned.iter() {
+            let indent = if owner.is_some() { "        " } else { "    " };
+            if let Some(entry) = owner {
+                out.push_str(&format!("    subgraph cluster_{} {{\n", label_name(*entry)));
+                out.push_str(&format!("        label=\"{}\";\n", label_name(*entry)));
+            }
+            for block in blocks {
+                out.push_str(&format!("{}{} [label=\"{}\"];\n", indent, label_name(block.start), self.block_label(block)));
+            }
+            if owner.is_some() {
+                out.push_str("    }\n");
+            }
+        }
+        for block in self.blocks.values() {
+            for &(edge, to) in block.succs.iter() {
+                let style = match edge {
+                    Edge::Next => "",
+                    Edge::Taken => " [color=blue]",
+                    Edge::Call => " [style=dashed, color=red]",
+                    Edge::AfterCall => " [style=dotted]",
+                };
+                out.push_str(&format!("    {} -> {}{};\n", label_name(block.start), label_name(to), style));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Graphviz source of the functions and who calls whom.
+    pub fn call_graph_dot(&self) -> String {
+        let mut out = String::from("digraph calls {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for f in self.functions.values() {
+            out.push_str(&format!("    {};\n", label_name(f.entry)));
+        }
+        for f in self.functions.values() {
+            for &callee in f.calls.iter() {
+                out.push_str(&format!("    {} -> {};\n", label_name(f.entry), label_name(callee)));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+    use crate::test_util::aoc_program;
+
+    // main calls twice, which calls out and returns
+    const CALLS: &str = "
+            arb #100
+            add #back, #0, rb+0
+            jt #1, #twice
+        back: hlt
+        twice: arb #1
+            add #ret, #0, rb+0
+            jt #1, #say
+        ret: arb #-1
+            jt #1, rb+0
+        say: in [val]
+            jf [val], #skip
+            out [val]
+        skip: jt #1, rb+0
+        val: .data 0
+    ";
+
+    #[test]
+    fn test_calls_and_returns() {
+        let prog = assemble(CALLS).unwrap();
+        let cfg = Cfg::new(&prog.code);
+        let starts: Vec<usize> = cfg.blocks.keys().copied().collect();
+        let (back, twice, ret, say, skip) = (9, 10, 19, 24, 31);
+        assert_eq!(starts, [0, back, twice, ret, say, 29, skip]);
+        assert_eq!(cfg.blocks[&0].exit, Exit::Call { target: twice, ret: back });
+        assert_eq!(cfg.blocks[&back].exit, Exit::Halt);
+        assert_eq!(cfg.blocks[&ret].exit, Exit::Return { cond: false });
+        assert_eq!(cfg.blocks[&say].exit, Exit::Jump { target: skip, cond: true });
+        assert_eq!(cfg.blocks[&say].succs, [(Edge::Taken, skip), (Edge::Next, 29)]);
+        assert_eq!(cfg.blocks[&29].exit, Exit::Next);
+
+        assert_eq!(cfg.functions.keys().copied().collect::<Vec<_>>(), [0, twice, say]);
+        assert_eq!(cfg.functions[&0].blocks, BTreeSet::from([0, back]));
+        assert_eq!(cfg.functions[&0].calls, BTreeSet::from([twice]));
+        assert_eq!(cfg.functions[&twice].calls, BTreeSet::from([say]));
+        assert_eq!(cfg.functions[&say].blocks, BTreeSet::from([say, 29, skip]));
+        assert!(cfg.functions[&say].calls.is_empty());
+    }
+
+    #[test]
+    fn test_dot() {
+        let prog = assemble("
+            loop: in [val]
+                jf [val], #end
+                out [val]
+                jt #1, #loop
+            end: hlt
+            val: .data 0
+        ").unwrap();
+        let cfg = Cfg::new(&prog.code);
+        assert_eq!(cfg.to_dot(), "digraph cfg {
+    node [shape=box, fontname=\"monospace\"];
+    subgraph cluster_L0 {
+        label=\"L0\";
+        L0 [label=\"L0:\\l    in [11]\\l    jf [11], #L10\\l\"];
+        L5 [label=\"L5:\\l    out [11]\\l    jt #1, #L0\\l\"];
+        L10 [label=\"L10:\\l    hlt\\l\"];
+    }
+    L0 -> L10 [color=blue];
+    L0 -> L5;
+    L5 -> L0 [color=blue];
+}
+");
+        assert_eq!(cfg.call_graph_dot(), "digraph calls {\n    node [shape=box, fontname=\"monospace\"];\n    L0;\n}\n");
+    }
+
+    #[test]
+    fn test_springdroid_functions() {
+        let prog = aoc_program!(21);
+        let cfg = Cfg::new(&prog.code);
+        assert_eq!(cfg.blocks[&1279].exit, Exit::Call { target: 1263, ret: 1288 });
+        assert_eq!(cfg.blocks[&1288].exit, Exit::Return { cond: false });
+        assert!(cfg.functions[&0].calls.is_superset(&BTreeSet::from([1378, 1337, 1279])));
+        assert!(cfg.functions[&1301].calls.is_superset(&BTreeSet::from([1279, 1421])));
+        assert_eq!(cfg.functions[&1279].calls, BTreeSet::from([1263]));
+        assert!(cfg.call_graph_dot().contains("    L1301 -> L1279;\n"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::int::{Computer, ComputerState, VmError};
+
+// how often a machine waiting for input checks for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// instructions executed between cancellation checks
+const CANCEL_CHECK_STEPS: u32 = 4096;
+
+/// Why a spawned machine stopped running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Halted,
+    Cancelled,
+    /// Waited longer than the input timeout.
+    InputTimeout,
+    /// Waiting for input after the input side was closed.
+    InputClosed,
+    /// Wrote a value after the output side was dropped.
+    OutputClosed,
+}
+
+/// Machine state after its thread finished.
+pub struct Finished {
+    pub comp: Computer,
+    pub exit: Exit,
+}
+
+/// Computer running on its own thread, fed and drained through channels.
+/// Values still queued in `ins` when spawning are read first. Dropping
+/// the machine without joining it cancels it.
+pub struct Machine {
+    input: Option<Sender<i64>>,
+    output: Receiver<i64>,
+    cancelled: Arc<AtomicBool>,
+    // only taken by `join`
+    handle: Option<JoinHandle<Result<Finished, VmError>>>,
+}
+
+fn machine_loop(
+    mut comp: Computer,
+    input: Receiver<i64>,
+    output: Sender<i64>,
+    cancelled: &AtomicBool,
+    input_timeout: Option<Duration>,
+) -> Result<Finished, VmError> {
+    let finished = |comp, exit| Ok(Finished { comp, exit });
+    let mut steps = 0u32;
+    loop {
+        steps += 1;
+        if steps == CANCEL_CHECK_STEPS {
+            steps = 0;
+            if cancelled.load(Ordering::Relaxed) {
+                return finished(comp, Exit::Cancelled);
+            }
+        }
+        match comp.step()? {
+            None | Some(ComputerState::BudgetExhausted) => {}
+            Some(ComputerState::WriteInt) => {
+                let val = comp.outs.pop_back().unwrap();
+                if output.send(val).is_err() {
+                    return finished(comp, Exit::OutputClosed);
+                }
+            }
+            Some(ComputerState::ReadInt) => {
+                let deadline = input_timeout.map(|t| Instant::now() + t);
+                loop {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return finished(comp, Exit::Cancelled);
+                    }
+                    let wait = match deadline {
+                        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                            Some(left) => left.min(POLL_INTERVAL),
+                            None => return finished(comp, Exit::InputTimeout),
+                        },
+                        None => POLL_INTERVAL,
+                    };
+                    match input.recv_timeout(wait) {
+                        Ok(val) => {
+                            comp.ins.push_back(val);
+                            break;
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => return finished(comp, Exit::InputClosed),
+                    }
+                }
+            }
+            Some(ComputerState::Halted) => return finished(comp, Exit::Halted),
+        }
+    }
+}
+
+impl Computer {
+    pub fn spawn(self) -> Machine {
+        Machine::spawn(self, None)
+    }
+
+    /// Spawns a machine that gives up after waiting `timeout` for an input.
+    pub fn spawn_with_input_timeout(self, timeout: Duration) -> Machine {
+        Machine::spawn(self, Some(timeout))
+    }
+}
+
+impl Machine {
+    fn spawn(comp: Computer, input_timeout: Option<Duration>) -> Machine {
+        let (input, machine_input) = mpsc::channel();
+        let (machine_output, output) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancelled);
+        let handle = thread::spawn(move || {
+            machine_loop(comp, machine_input, machine_output, &flag, input_timeout)
+        });
+        Machine { input: Some(input), output, cancelled, handle: Some(handle) }
+    }
+
+    /// Queues an input, `false` once the machine stopped or the input was closed.
+    pub fn send(&self, val: i64) -> bool {
+        self.input.as_ref().is_some_and(|input| input.send(val).is_ok())
+    }
+
+    pub fn send_all(&self, vals: impl IntoIterator<Item = i64>) -> bool {
+        vals.into_iter().all(|val| self.send(val))
+    }
+
+    /// No more inputs, a machine asking for one afterwards stops.
+    pub fn close_input(&mut self) {
+        self.input = None;
+    }
+
+    /// Next output, `None` once the machine stopped and everything it
+    /// wrote was read.
+    pub fn recv(&self) -> Option<i64> {
+        self.output.recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<i64, RecvTimeoutError> {
+        self.output.recv_timeout(timeout)
+    }
+
+    /// Blocking iterator over the outputs, ends when the machine stops.
+    pub fn outputs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.output.iter()
+    }
+
+    /// Asks the machine to stop, it exits with `Exit::Cancelled` shortly after.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|handle| handle.is_finished())
+    }
+
+    /// Closes the input and waits for the machine to stop, cancel first if
+    /// it may spin forever without asking for input. Outputs nobody read
+    /// are left in the returned computer's `outs`.
+    pub fn join(mut self) -> Result<Finished, VmError> {
+        self.close_input();
+        let handle = self.handle.take().unwrap();
+        let mut finished = handle.join().expect("Machine thread panicked")?;
+        finished.comp.outs.extend(self.output.try_iter());
+        Ok(finished)
+    }
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, program::Program};
+
+    fn computer(prog: &[i64]) -> Computer {
+        Computer::new(&Program::from(prog))
+    }
+
+    #[test]
+    fn test_producer_consumer() {
+        // doubles every input until it reads 0
+        let doubler = assemble("
+            loop: in [val]
+                jf [val], #end
+                mul [val], #2, [val]
+                out [val]
+                jf #0, #loop
+            end: hlt
+            val: .data 0
+        ").unwrap();
+        let machine = computer(&doubler.code).with_input([1]).spawn();
+        assert_eq!(machine.recv(), Some(2));
+        assert!(machine.send_all([5, 7]));
+        assert_eq!(machine.outputs().take(2).collect::<Vec<_>>(), [10, 14]);
+        machine.send(0);
+        assert_eq!(machine.recv(), None);
+        let finished = machine.join().unwrap();
+        assert_eq!(finished.exit, Exit::Halted);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let machine = computer(&[3, 0, 4, 0, 99]).spawn_with_input_timeout(Duration::from_millis(20));
+        assert_eq!(machine.recv_timeout(Duration::from_millis(5)), Err(RecvTimeoutError::Timeout));
+        // the output closes once it gave up, joining earlier closes the input
+        assert_eq!(machine.recv(), None);
+        assert_eq!(machine.join().unwrap().exit, Exit::InputTimeout);
+
+        let mut machine = computer(&[3, 0, 4, 0, 99]).spawn();
+        machine.close_input();
+        assert_eq!(machine.join().unwrap().exit, Exit::InputClosed);
+    }
+
+    #[test]
+    fn test_cancel() {
+        // spins forever without any I/O
+        let machine = computer(&[1105, 1, 0]).spawn();
+        machine.cancel();
+        assert_eq!(machine.join().unwrap().exit, Exit::Cancelled);
+
+        let machine = computer(&[3, 0, 99]).spawn();
+        machine.cancel();
+        assert_eq!(machine.join().unwrap().exit, Exit::Cancelled);
+    }
+
+    #[test]
+    fn test_join_closes_input() {
+        let machine = computer(&[3, 0, 4, 0, 3, 0, 99]).spawn();
+        machine.send(5);
+        assert_eq!(machine.recv(), Some(5));
+        let finished = machine.join().unwrap();
+        assert_eq!((finished.exit, finished.comp.eip), (Exit::InputClosed, 4));
+    }
+
+    #[test]
+    fn test_drop_cancels() {
+        let machine = computer(&[1105, 1, 0]).spawn();
+        let cancelled = Arc::clone(&machine.cancelled);
+        drop(machine);
+        assert!(cancelled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_join_keeps_unread_outputs() {
+        let machine = computer(&[104, 1, 104, 2, 99]).spawn();
+        let finished = machine.join().unwrap();
+        assert_eq!(finished.comp.outs, [1, 2]);
+
+        let machine = computer(&[3, 0, 42]).with_input([1]).spawn();
+        assert_eq!(machine.join().err(), Some(VmError::UnknownOpCode { eip: 2, code: 42 }));
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+
+/// A token sequence written as a main routine calling functions, the way
+/// the vacuum robot takes its movement rules. Functions are named `A`, `B`,
+/// ... in order of their first call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compression<T> {
+    /// Indices into `functions`, one per call.
+    pub main: Vec<usize>,
+    pub functions: Vec<Vec<T>>,
+}
+
+/// Functions there are names for.
+pub const MAX_FUNCTIONS: usize = 26;
+
+pub fn function_name(ix: usize) -> char {
+    assert!(ix < MAX_FUNCTIONS, "No name for function {}", ix);
+    char::from(b'A' + ix as u8)
+}
+
+/// Tokens separated by commas, without the newline.
+pub fn encode<T: fmt::Display>(tokens: &[T]) -> String {
+    tokens.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(",")
+}
+
+// what `encode` makes of `tokens` without building it
+fn encoded_len<T: fmt::Display>(tokens: &[T]) -> usize {
+    tokens.iter().map(|t| t.to_string().len() + 1).sum::<usize>().saturating_sub(1)
+}
+
+impl<T: Clone + fmt::Display> Compression<T> {
+    pub fn main_routine(&self) -> String {
+        encode(&self.main.iter().map(|&ix| function_name(ix)).collect::<Vec<_>>())
+    }
+
+    /// Main routine then every function, one line each, newlines included.
+    pub fn to_lines(&self) -> String {
+        let mut lines = self.main_routine() + "\n";
+        for function in &self.functions {
+            lines += &encode(function);
+            lines.push('\n');
+        }
+        lines
+    }
+
+    /// Characters on all the lines, newlines left out.
+    pub fn encoded_len(&self) -> usize {
+        (2 * self.main.len()).saturating_sub(1) + self.functions.iter().map(|f| encoded_len(f)).sum::<usize>()
+    }
+
+    /// The tokens the main routine stands for.
+    pub fn expand(&self) -> Vec<T> {
+        self.main.iter().flat_map(|&ix| self.functions[ix].iter().cloned()).collect()
+    }
+}
+
+/// Splits token sequences into a main routine and up to `functions`
+/// functions, no line encoding longer than `max_len` characters. There are
+/// never more than `MAX_FUNCTIONS` functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compressor {
+    pub functions: usize,
+    pub max_len: usize,
+}
+
+impl Compressor {
+    pub fn new(functions: usize, max_len: usize) -> Compressor {
+        Compressor { functions: functions.min(MAX_FUNCTIONS), max_len }
+    }
+
+    /// Every way to write `tokens`, with no two functions alike. Solutions
+    /// leaving functions unused have fewer of them.
+    pub fn all<T: Clone + PartialEq + fmt::Display>(&self, tokens: &[T]) -> Vec<Compression<T>> {
+        let mut found = vec![];
+        self.search(tokens, 0, &mut vec![], &mut vec![], &mut |main, functions| {
+            let functions = functions.iter().map(|f| f.to_vec()).collect();
+            found.push(Compression { main: main.to_vec(), functions });
+        });
+        found
+    }
+
+    /// The solution with the fewest calls, then the fewest characters.
+    pub fn shortest<T: Clone + PartialEq + fmt::Display>(&self, tokens: &[T]) -> Option<Compression<T>> {
+        self.all(tokens).into_iter().min_by_key(|c| (c.main.len(), c.encoded_len()))
+    }
+
+    // covers tokens[at..] calling known functions or defining a new one
+    // starting at `at`
+    fn search<'t, T: PartialEq + fmt::Display>(
+        &self,
+        tokens: &'t [T],
+        at: usize,
+        main: &mut Vec<usize>,
+        functions: &mut Vec<&'t [T]>,
+        found: &mut impl FnMut(&[usize], &[&'t [T]]),
+    ) {
+        if at == tokens.len() {
+            if !main.is_empty() {
+                found(main, functions);
+            }
+            return;
+        }
+        // one more call and its comma
+        if 2 * main.len() + 1 > self.max_len {
+            return;
+        }
+        for ix in 0..functions.len() {
+            if tokens[at..].starts_with(functions[ix]) {
+                main.push(ix);
+                self.search(tokens, at + functions[ix].len(), main, functions, found);
+                main.pop();
+            }
+        }
+        if functions.len() >= self.functions.min(MAX_FUNCTIONS) {
+            return;
+        }
+        for end in at + 1..=tokens.len() {
+            let function = &tokens[at..end];
+            if encoded_len(function) > self.max_len {
+                break;
+            }
+            if functions.contains(&function) {
+                continue;
+            }
+            main.push(functions.len());
+            functions.push(function);
+            self.search(tokens, end, main, functions, found);
+            functions.pop();
+            main.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the example from the puzzle, turns and moves as separate tokens
+    const EXAMPLE: &str = "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2";
+
+    fn tokens(s: &str) -> Vec<String> {
+        s.split(',').map(|t| t.to_owned()).collect()
+    }
+
+    #[test]
+    fn test_example() {
+        let example = tokens(EXAMPLE);
+        let all = Compressor::new(3, 20).all(&example);
+        assert!(all.iter().all(|c| c.expand() == example));
+        assert!(all.iter().all(|c| c.to_lines().lines().all(|l| l.len() <= 20)));
+        let puzzle = Compression {
+            main: vec![0, 1, 2, 1, 0, 2],
+            functions: vec![tokens("R,8,R,8"), tokens("R,4,R,4,R,8"), tokens("L,6,L,2")],
+        };
+        assert!(all.contains(&puzzle));
+        assert_eq!(puzzle.to_lines(), "A,B,C,B,A,C\nR,8,R,8\nR,4,R,4,R,8\nL,6,L,2\n");
+
+        let shortest = Compressor::new(3, 20).shortest(&example).unwrap();
+        assert!(shortest.main.len() <= puzzle.main.len());
+        assert_eq!(shortest.expand(), example);
+    }
+
+    #[test]
+    fn test_limits() {
+        let abab = tokens("a,b,a,b");
+        assert_eq!(Compressor::new(1, 3).all(&abab), [Compression { main: vec![0, 0], functions: vec![tokens("a,b")] }]);
+        // a single call of everything is too long, two calls need a function
+        assert_eq!(Compressor::new(1, 3).shortest(&tokens("a,b,c")), None);
+        assert_eq!(Compressor::new(0, 20).shortest(&abab), None);
+        let shortest = Compressor::new(2, 20).shortest(&abab).unwrap();
+        assert_eq!(shortest.main_routine(), "A");
+        assert!(Compressor::new(2, 20).all::<String>(&[]).is_empty());
+        assert_eq!(Compressor::new(100, 20).functions, MAX_FUNCTIONS);
+        assert_eq!(function_name(MAX_FUNCTIONS - 1), 'Z');
+        assert_eq!(Compression::<String> { main: vec![], functions: vec![] }.encoded_len(), 0);
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeSet;
+
+use crate::int::{Computer, ComputerState, VmError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    Addr(usize),
+    /// Stops at `eip` only while memory at `addr` holds `val`.
+    AddrIf { eip: usize, addr: usize, val: i64 },
+    /// Stops wherever execution is once memory at `addr` holds `val`.
+    MemEquals { addr: usize, val: i64 },
+}
+
+impl Breakpoint {
+    fn hit(&self, comp: &Computer) -> bool {
+        match *self {
+            Breakpoint::Addr(eip) => comp.eip == eip,
+            Breakpoint::AddrIf { eip, addr, val } => comp.eip == eip && comp.mem[addr] == val,
+            Breakpoint::MemEquals { addr, val } => comp.mem[addr] == val,
+        }
+    }
+}
+
+/// Reason the debugger gave control back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    Breakpoint(Breakpoint),
+    /// Instruction at `eip` stored to a watched address.
+    Watchpoint { eip: usize, addr: usize, old: i64, new: i64 },
+    NeedInput,
+    Halted,
+}
+
+pub struct Debugger {
+    pub comp: Computer,
+    pub breakpoints: Vec<Breakpoint>,
+    pub watchpoints: BTreeSet<usize>,
+    /// Instructions executed under the debugger.
+    pub steps: u64,
+}
+
+impl Debugger {
+    pub fn new(comp: Computer) -> Debugger {
+        Debugger {
+            comp,
+            breakpoints: vec![],
+            watchpoints: BTreeSet::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) {
+        if !self.breakpoints.contains(&bp) {
+            self.breakpoints.push(bp);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, bp: Breakpoint) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|&b| b != bp);
+        self.breakpoints.len() != len
+    }
+
+    pub fn watch(&mut self, addr: usize) {
+        self.watchpoints.insert(addr);
+    }
+
+    pub fn unwatch(&mut self, addr: usize) -> bool {
+        self.watchpoints.remove(&addr)
+    }
+
+    /// Executes one instruction. Outputs don't stop the debugger, they stay
+    /// queued in `comp.outs`.
+    pub fn step(&mut self) -> Result<Option<Stop>, VmError> {
+        let eip = self.comp.eip;
+        let op = self.comp.fetch()?;
+        let watched = op.write_addr()
+            .and_then(|addr| usize::try_from(addr).ok())
+            .filter(|addr| self.watchpoints.contains(addr))
+            .map(|addr| (addr, self.comp.mem[addr]));
+
+        match self.comp.execute(op)? {
+            Some(ComputerState::ReadInt) => return Ok(Some(Stop::NeedInput)),
+            Some(ComputerState::Halted) => return Ok(Some(Stop::Halted)),
+            Some(ComputerState::WriteInt | ComputerState::BudgetExhausted) | None => self.steps += 1,
+        }
+        Ok(watched.map(|(addr, old)| Stop::Watchpoint { eip, addr, old, new: self.comp.mem[addr] }))
+    }
+
+    /// Runs until a breakpoint, a watchpoint, an input request or halt.
+    /// At least one instruction executes, so continuing off a breakpoint works.
+    pub fn run(&mut self) -> Result<Stop, VmError> {
+        loop {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+            if let Some(&bp) = self.breakpoints.iter().find(|bp| bp.hit(&self.comp)) {
+                return Ok(Stop::Breakpoint(bp));
+            }
+        }
+    }
+
+    /// Runs with an extra one-off breakpoint.
+    pub fn run_until(&mut self, bp: Breakpoint) -> Result<Stop, VmError> {
+        let is_new = !self.breakpoints.contains(&bp);
+        self.add_breakpoint(bp);
+        let stop = self.run();
+        if is_new {
+            self.remove_breakpoint(bp);
+        }
+        stop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assembled, COUNTDOWN};
+
+    fn debugger(src: &str) -> Debugger {
+        Debugger::new(assembled(src))
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut dbg = debugger(COUNTDOWN);
+        dbg.add_breakpoint(Breakpoint::Addr(4));
+        assert_eq!(dbg.run(), Ok(Stop::Breakpoint(Breakpoint::Addr(4))));
+        assert_eq!(dbg.run(), Ok(Stop::Breakpoint(Breakpoint::Addr(4))));
+        assert_eq!(dbg.comp.outs, [3]);
+
+        dbg.remove_breakpoint(Breakpoint::Addr(4));
+        let cond = Breakpoint::AddrIf { eip: 10, addr: 14, val: 1 };
+        assert_eq!(dbg.run_until(cond), Ok(Stop::Breakpoint(cond)));
+        assert_eq!(dbg.comp.outs, [3, 2]);
+        assert!(dbg.breakpoints.is_empty());
+
+        let mem = Breakpoint::MemEquals { addr: 14, val: 0 };
+        assert_eq!(dbg.run_until(mem), Ok(Stop::Breakpoint(mem)));
+        assert_eq!(dbg.comp.eip, 10);
+        assert_eq!(dbg.run(), Ok(Stop::Halted));
+        assert_eq!(dbg.comp.outs, [3, 2, 1]);
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        let mut dbg = debugger(COUNTDOWN);
+        dbg.watch(14);
+        assert_eq!(dbg.run(), Ok(Stop::Watchpoint { eip: 0, addr: 14, old: 0, new: 3 }));
+        assert_eq!(dbg.run(), Ok(Stop::Watchpoint { eip: 6, addr: 14, old: 3, new: 2 }));
+        assert_eq!(dbg.steps, 3);
+    }
+
+    #[test]
+    fn test_step_and_input() {
+        let mut dbg = debugger("in [5]\nout [5]\nhlt");
+        assert_eq!(dbg.step(), Ok(Some(Stop::NeedInput)));
+        assert_eq!(dbg.steps, 0);
+        dbg.comp.ins.push_back(42);
+        assert_eq!(dbg.step(), Ok(None));
+        assert_eq!(dbg.step(), Ok(None));
+        assert_eq!(dbg.comp.outs, [42]);
+        assert_eq!(dbg.step(), Ok(Some(Stop::Halted)));
+    }
+}
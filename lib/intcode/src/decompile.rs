@@ -0,0 +1,587 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{
+    cfg::{BasicBlock, Cfg, Edge, Exit},
+    disasm::decode_at,
+    int::{Instruction, OpCode, OpParamMode, OpParamType},
+};
+
+const INDENT: &str = "    ";
+
+/// Stack frame of a function: `rb` offsets relative to `rb` at its entry.
+struct Frame {
+    /// Size allocated by the `arb` on entry, 0 for main.
+    size: i64,
+    /// Address of that `arb` and of the ones freeing it before returns.
+    arbs: BTreeSet<usize>,
+    /// `rb` offset at the start of each block.
+    deltas: HashMap<usize, i64>,
+    params: BTreeSet<i64>,
+    locals: BTreeSet<i64>,
+}
+
+struct Func<'a> {
+    entry: usize,
+    frame: Frame,
+    order: Vec<&'a BasicBlock>,
+}
+
+// where control goes when a range of blocks ends, and the enclosing loop
+#[derive(Clone, Copy)]
+struct Ctx {
+    depth: usize,
+    follow: usize,
+    loop_head: Option<usize>,
+    loop_exit: Option<usize>,
+}
+
+enum Line {
+    Label(usize),
+    Text(String),
+}
+
+// function body being emitted, labels are only printed if jumped to
+#[derive(Default)]
+struct Body {
+    lines: Vec<Line>,
+    gotos: BTreeSet<usize>,
+}
+
+impl Body {
+    fn line(&mut self, depth: usize, text: &str) {
+        self.lines.push(Line::Text(format!("{}{}", INDENT.repeat(depth), text)));
+    }
+}
+
+pub fn function_name(entry: usize) -> String {
+    if entry == 0 { "main".to_owned() } else { format!("f{}", entry) }
+}
+
+fn instr_at(code: &[i64], addr: usize) -> (Instruction, &[i64]) {
+    decode_at(code, addr).unwrap()
+}
+
+/// Best-effort pseudo-Rust for a program: one `fn` per function the
+/// calling convention reveals, loops and ifs recovered from the jumps,
+/// `rb` slots named as params (`a1`), locals (`v2`) and outgoing call
+/// arguments (`arg1`), and every other address as a global `gN`.
+/// Control flow it can't structure comes out as `goto`.
+pub struct Decompiler<'a> {
+    code: &'a [i64],
+    cfg: Cfg,
+    /// Operand words some instruction writes to.
+    patched: BTreeSet<usize>,
+}
+
+impl<'a> Decompiler<'a> {
+    pub fn new(code: &'a [i64]) -> Decompiler<'a> {
+        let cfg = Cfg::new(code);
+        let instrs = cfg.blocks.values().flat_map(|block| block.instrs.iter().copied());
+        let mut operands = BTreeSet::new();
+        let mut writes = BTreeSet::new();
+        for addr in instrs {
+            let (instr, args) = instr_at(code, addr);
+            operands.extend(addr + 1..addr + instr.code.size());
+            for ((ptype, mode), &val) in instr.params().zip(args) {
+                if ptype == OpParamType::Write && mode == OpParamMode::Position {
+                    writes.insert(val as usize);
+                }
+            }
+        }
+        let patched = operands.intersection(&writes).copied().collect();
+        Decompiler { code, cfg, patched }
+    }
+
+    pub fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+
+    fn frame(&self, entry: usize, blocks: &BTreeSet<usize>) -> Frame {
+        let (size, mut arbs, start_delta) = match instr_at(self.code, entry) {
+            (instr, args) if instr.code == OpCode::SpAdd && instr.modes[0] == OpParamMode::Immediate => {
+                // main sets up the stack, functions allocate their frame
+                let size = if entry == 0 { 0 } else { args[0] };
+                (size, BTreeSet::from([entry]), size.wrapping_sub(args[0]))
+            }
+            _ => (0, BTreeSet::new(), 0),
+        };
+
+        // rb offsets through the body, the first path reaching a block wins
+        let mut deltas = HashMap::new();
+        let mut stack = vec![(entry, start_delta)];
+        while let Some((start, delta)) = stack.pop() {
+            if !blocks.contains(&start) || deltas.contains_key(&start) {
+                continue;
+            }
+            deltas.insert(start, delta);
+            let block = &self.cfg.blocks[&start];
+            let mut end_delta = delta;
+            for (i, &addr) in block.instrs.iter().enumerate() {
+                let (instr, args) = instr_at(self.code, addr);
+                if instr.code == OpCode::SpAdd && instr.modes[0] == OpParamMode::Immediate {
+                    end_delta = end_delta.wrapping_add(args[0]);
+                    let frees = size != 0 && size.checked_neg() == Some(args[0]) && block.instrs.len() == i + 2;
+                    if frees && matches!(block.exit, Exit::Return { .. }) {
+                        arbs.insert(addr);
+                    }
+                }
+            }
+            for &(edge, to) in block.succs.iter() {
+                if edge != Edge::Call {
+                    stack.push((to, end_delta));
+                }
+            }
+        }
+
+        // params are the frame slots read before they're written
+        let mut first_access: BTreeMap<i64, bool> = BTreeMap::new();
+        for &start in blocks.iter() {
+            let mut delta = deltas.get(&start).copied().unwrap_or(0);
+            for &addr in self.cfg.blocks[&start].instrs.iter() {
+                let (instr, args) = instr_at(self.code, addr);
+                for ((ptype, mode), &val) in instr.params().zip(args) {
+                    if mode == OpParamMode::Relative {
+                        first_access.entry(delta.wrapping_add(val)).or_insert(ptype == OpParamType::Read);
+                    }
+                }
+                if instr.code == OpCode::SpAdd && instr.modes[0] == OpParamMode::Immediate {
+                    delta = delta.wrapping_add(args[0]);
+                }
+            }
+        }
+        let in_frame = |s: i64| s >= 1 && s <= size;
+        let params = first_access.iter().filter(|&(&s, &read)| in_frame(s) && read).map(|(&s, _)| s).collect();
+        let locals = first_access.iter().filter(|&(&s, &read)| in_frame(s) && !read).map(|(&s, _)| s).collect();
+        Frame { size, arbs, deltas, params, locals }
+    }
+
+    fn slot(&self, func: &Func, s: i64) -> String {
+        let frame = &func.frame;
+        if s > frame.size {
+            format!("arg{}", s.wrapping_sub(frame.size))
+        } else if s >= 1 {
+            if frame.params.contains(&s) { format!("a{}", s) } else { format!("v{}", s) }
+        } else if s == 0 && func.entry != 0 {
+            "ret_addr".to_owned()
+        } else {
+            format!("up{}", s.unsigned_abs())
+        }
+    }
+
+    // `word` is where the operand sits, code that patches it gets the value
+    // from memory at run time
+    fn operand(&self, func: &Func, delta: i64, word: usize, mode: OpParamMode, val: i64) -> String {
+        if self.patched.contains(&word) {
+            return match mode {
+                OpParamMode::Position => format!("mem[g{}]", word),
+                OpParamMode::Immediate => format!("g{}", word),
+                OpParamMode::Relative => format!("mem[rb + g{}]", word),
+            };
+        }
+        match mode {
+            OpParamMode::Position => format!("g{}", val),
+            OpParamMode::Immediate => val.to_string(),
+            OpParamMode::Relative => self.slot(func, delta.wrapping_add(val)),
+        }
+    }
+
+    // statement for a non-jump instruction, `None` for bookkeeping ones
+    fn statement(&self, func: &Func, delta: i64, addr: usize) -> Option<String> {
+        let (instr, args) = instr_at(self.code, addr);
+        let op = |i: usize| self.operand(func, delta, addr + 1 + i, instr.modes[i], args[i]);
+        let imm = |i: usize, val: i64| instr.modes[i] == OpParamMode::Immediate && args[i] == val;
+        let neg = |i: usize| instr.modes[i] == OpParamMode::Immediate && args[i] < 0 && !self.patched.contains(&(addr + 1 + i));
+        Some(match instr.code {
+            OpCode::Add if imm(1, 0) => format!("{} = {};", op(2), op(0)),
+            OpCode::Add if imm(0, 0) => format!("{} = {};", op(2), op(1)),
+            OpCode::Add if op(0) == op(2) && neg(1) => format!("{} -= {};", op(2), args[1].unsigned_abs()),
+            OpCode::Add if op(0) == op(2) => format!("{} += {};", op(2), op(1)),
+            OpCode::Add if neg(1) => format!("{} = {} - {};", op(2), op(0), args[1].unsigned_abs()),
+            OpCode::Add => format!("{} = {} + {};", op(2), op(0), op(1)),
+            OpCode::Mul if imm(1, 1) => format!("{} = {};", op(2), op(0)),
+            OpCode::Mul if imm(0, 1) => format!("{} = {};", op(2), op(1)),
+            OpCode::Mul if imm(1, -1) => format!("{} = -{};", op(2), op(0)),
+            OpCode::Mul if imm(0, -1) => format!("{} = -{};", op(2), op(1)),
+            OpCode::Mul => format!("{} = {} * {};", op(2), op(0), op(1)),
+            OpCode::CmpLess => format!("{} = ({} < {}) as i64;", op(2), op(0), op(1)),
+            OpCode::CmpEquals => format!("{} = ({} == {}) as i64;", op(2), op(0), op(1)),
+            OpCode::Read => format!("{} = input();", op(0)),
+            OpCode::Write => format!("output({});", op(0)),
+            OpCode::SpAdd if func.frame.arbs.contains(&addr) => return None,
+            OpCode::SpAdd => format!("rb += {};", op(0)),
+            OpCode::Halt => "halt();".to_owned(),
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => return None,
+        })
+    }
+
+    // condition under which the jump ending `block` is taken
+    fn condition(&self, func: &Func, block: &BasicBlock, taken: bool) -> String {
+        let delta = self.block_end_delta(func, block);
+        let addr = *block.instrs.last().unwrap();
+        let (instr, args) = instr_at(self.code, addr);
+        let val = self.operand(func, delta, addr + 1, instr.modes[0], args[0]);
+        let nonzero = (instr.code == OpCode::JumpIfTrue) == taken;
+
+        // inline a comparison computed right before into a fresh slot
+        if let Some(&prev) = block.instrs.len().checked_sub(2).map(|i| &block.instrs[i]) {
+            let (cmp, cargs) = instr_at(self.code, prev);
+            let is_cmp = matches!(cmp.code, OpCode::CmpLess | OpCode::CmpEquals);
+            if is_cmp && cmp.modes[2] == instr.modes[0] && cargs[2] == args[0] {
+                let a = self.operand(func, delta, prev + 1, cmp.modes[0], cargs[0]);
+                let b = self.operand(func, delta, prev + 2, cmp.modes[1], cargs[1]);
+                if a != val && b != val {
+                    let (yes, no) = if cmp.code == OpCode::CmpLess { ("<", ">=") } else { ("==", "!=") };
+                    return format!("{} {} {}", a, if nonzero { yes } else { no }, b);
+                }
+            }
+        }
+        format!("{} {} 0", val, if nonzero { "!=" } else { "==" })
+    }
+
+    fn block_end_delta(&self, func: &Func, block: &BasicBlock) -> i64 {
+        let mut delta = func.frame.deltas.get(&block.start).copied().unwrap_or(0);
+        for &addr in block.instrs.iter() {
+            let (instr, args) = instr_at(self.code, addr);
+            if instr.code == OpCode::SpAdd && instr.modes[0] == OpParamMode::Immediate {
+                delta = delta.wrapping_add(args[0]);
+            }
+        }
+        delta
+    }
+
+    fn jump_text(&self, ctx: Ctx, target: usize, gotos: &mut BTreeSet<usize>) -> Option<String> {
+        if target == ctx.follow {
+            None
+        } else if Some(target) == ctx.loop_head {
+            Some("continue;".to_owned())
+        } else if Some(target) == ctx.loop_exit {
+            Some("break;".to_owned())
+        } else {
+            gotos.insert(target);
+            Some(format!("goto L{};", target))
+        }
+    }
+
+    // the block's statements, without its exit
+    fn emit_stmts(&self, func: &Func, block: &BasicBlock, depth: usize, body: &mut Body) {
+        let mut delta = func.frame.deltas.get(&block.start).copied().unwrap_or(0);
+        let push = match block.exit {
+            // the return address store is part of the call
+            Exit::Call { .. } => block.instrs.len().checked_sub(2).map(|i| block.instrs[i]),
+            _ => None,
+        };
+        body.lines.push(Line::Label(block.start));
+        for &addr in block.instrs.iter() {
+            if Some(addr) != push {
+                if let Some(stmt) = self.statement(func, delta, addr) {
+                    body.line(depth, &stmt);
+                }
+            }
+            let (instr, args) = instr_at(self.code, addr);
+            if instr.code == OpCode::SpAdd && instr.modes[0] == OpParamMode::Immediate {
+                delta = delta.wrapping_add(args[0]);
+            }
+        }
+    }
+
+    // outgoing argument slots a call block fills in
+    fn call_args(&self, func: &Func, block: &BasicBlock) -> Vec<String> {
+        let delta = self.block_end_delta(func, block);
+        let slots: BTreeSet<i64> = block.instrs.iter()
+            .filter_map(|&addr| {
+                let (instr, args) = instr_at(self.code, addr);
+                let slot = instr.params().zip(args)
+                    .find(|&((ptype, mode), _)| ptype == OpParamType::Write && mode == OpParamMode::Relative)
+                    .map(|(_, &k)| delta.wrapping_add(k));
+                slot
+            })
+            .filter(|&s| s > func.frame.size)
+            .collect();
+        slots.into_iter().map(|s| self.slot(func, s)).collect()
+    }
+
+    fn emit_block(&self, func: &Func, ix: usize, ctx: Ctx, last: bool, body: &mut Body) {
+        let block = func.order[ix];
+        self.emit_stmts(func, block, ctx.depth, body);
+
+        let Body { lines, gotos } = body;
+        let mut line = |s: String| lines.push(Line::Text(format!("{}{}", INDENT.repeat(ctx.depth), s)));
+        let falls = match block.exit {
+            Exit::Next => true,
+            Exit::Jump { target, cond: false } => {
+                if let Some(jump) = self.jump_text(ctx, target, gotos) {
+                    line(jump);
+                }
+                false
+            }
+            // loop latch, jumps back unless the loop is done
+            Exit::Jump { target, cond: true } if last && Some(target) == ctx.loop_head => {
+                line(format!("if {} {{ break; }}", self.condition(func, block, false)));
+                false
+            }
+            Exit::Jump { target, cond: true } => {
+                let jump = self.jump_text(ctx, target, gotos).unwrap_or_default();
+                line(format!("if {} {{ {} }}", self.condition(func, block, true), jump));
+                true
+            }
+            Exit::Call { target, ret } => {
+                // a patched call target is a function pointer
+                let jump = *block.instrs.last().unwrap();
+                let callee = if self.patched.contains(&(jump + 2)) {
+                    format!("(*g{})", jump + 2)
+                } else {
+                    function_name(target)
+                };
+                line(format!("{}({});", callee, self.call_args(func, block).join(", ")));
+                ret == block.end || {
+                    gotos.insert(ret);
+                    line(format!("goto L{};", ret));
+                    false
+                }
+            }
+            Exit::Return { cond: true } => {
+                line(format!("if {} {{ return; }}", self.condition(func, block, true)));
+                true
+            }
+            Exit::Return { cond: false } => {
+                line("return;".to_owned());
+                false
+            }
+            Exit::Indirect { cond } => {
+                let addr = *block.instrs.last().unwrap();
+                let (instr, args) = instr_at(self.code, addr);
+                let target = self.operand(func, self.block_end_delta(func, block), addr + 2, instr.modes[1], args[1]);
+                if cond {
+                    line(format!("if {} {{ goto *{}; }}", self.condition(func, block, true), target));
+                } else {
+                    line(format!("goto *{};", target));
+                }
+                cond
+            }
+            Exit::Halt => false,
+            Exit::End => {
+                line(format!("// runs into data at {}", block.end));
+                false
+            }
+        };
+        // falling out of a loop body leaves the loop
+        if falls && last && ctx.loop_head == Some(ctx.follow) {
+            line("break;".to_owned());
+        } else if falls && block.end != ctx.follow {
+            if let Some(jump) = self.jump_text(ctx, block.end, gotos) {
+                line(jump);
+            }
+        }
+    }
+
+    fn index_of(&self, func: &Func, addr: usize) -> Option<usize> {
+        func.order.iter().position(|b| b.start == addr)
+    }
+
+    // emits blocks `lo..hi` of the function, which run on into `ctx.follow`
+    fn emit_range(&self, func: &Func, lo: usize, hi: usize, ctx: Ctx, skip_loop: bool, body: &mut Body) {
+        let depth = ctx.depth;
+        let mut i = lo;
+        while i < hi {
+            let block = func.order[i];
+            let follow_of = |j: usize| func.order.get(j).map_or(ctx.follow, |b| if j < hi { b.start } else { ctx.follow });
+
+            // loop: the last block in range jumping back here
+            let latch = (i..hi).rev().find(|&k| {
+                func.order[k].succs.iter().any(|&(e, to)| e == Edge::Taken && to == block.start)
+            });
+            if let Some(k) = latch.filter(|_| !(skip_loop && i == lo)) {
+                let exit = follow_of(k + 1);
+                body.line(depth, "loop {");
+                let inner = Ctx { depth: depth + 1, follow: block.start, loop_head: Some(block.start), loop_exit: Some(exit) };
+                self.emit_range(func, i, k + 1, inner, true, body);
+                body.line(depth, "}");
+                i = k + 1;
+                continue;
+            }
+
+            // if and if/else: a conditional jump forward over the then part
+            if let Exit::Jump { target, cond: true } = block.exit {
+                let j = self.index_of(func, target).filter(|&j| j > i + 1 && j <= hi)
+                    .or_else(|| (target == ctx.follow && i + 1 < hi).then_some(hi));
+                if let Some(j) = j {
+                    let skip_ctx = ctx.loop_head.is_some_and(|h| h == target) || ctx.loop_exit == Some(target);
+                    if !skip_ctx {
+                        self.emit_stmts(func, block, depth, body);
+                        let cond = self.condition(func, block, false);
+                        let then_last = func.order[j - 1];
+                        let else_end = match then_last.exit {
+                            Exit::Jump { target: y, cond: false } if y > target => {
+                                self.index_of(func, y).filter(|&m| m > j && m <= hi).map(|m| (m, y))
+                                    .or_else(|| (y == ctx.follow && j < hi).then_some((hi, y)))
+                            }
+                            _ => None,
+                        };
+                        body.line(depth, &format!("if {} {{", cond));
+                        match else_end {
+                            Some((m, y)) => {
+                                let inner = Ctx { depth: depth + 1, follow: y, ..ctx };
+                                self.emit_range(func, i + 1, j, inner, false, body);
+                                body.line(depth, "} else {");
+                                self.emit_range(func, j, m, inner, false, body);
+                                body.line(depth, "}");
+                                i = m;
+                            }
+                            None => {
+                                let inner = Ctx { depth: depth + 1, follow: target, ..ctx };
+                                self.emit_range(func, i + 1, j, inner, false, body);
+                                body.line(depth, "}");
+                                i = j;
+                            }
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            let range_ctx = Ctx { follow: follow_of(i + 1), ..ctx };
+            self.emit_block(func, i, range_ctx, i + 1 == hi, body);
+            i += 1;
+        }
+    }
+
+    fn function(&self, entry: usize) -> String {
+        let f = &self.cfg.functions[&entry];
+        let func = Func {
+            entry,
+            frame: self.frame(entry, &f.blocks),
+            order: f.blocks.iter().map(|s| &self.cfg.blocks[s]).collect(),
+        };
+        let mut body = Body::default();
+        let ctx = Ctx { depth: 1, follow: usize::MAX, loop_head: None, loop_exit: None };
+        self.emit_range(&func, 0, func.order.len(), ctx, false, &mut body);
+
+        let params: Vec<String> = func.frame.params.iter().map(|&s| format!("{}: i64", self.slot(&func, s))).collect();
+        let mut out = format!("fn {}({}) {{\n", function_name(entry), params.join(", "));
+        if entry == 0 {
+            if let Some(&arb) = func.frame.arbs.first() {
+                out.push_str(&format!("{}// stack at {}\n", INDENT, instr_at(self.code, arb).1[0]));
+            }
+        }
+        for &s in func.frame.locals.iter() {
+            out.push_str(&format!("{}let mut {}: i64;\n", INDENT, self.slot(&func, s)));
+        }
+        for line in body.lines {
+            match line {
+                Line::Label(addr) if body.gotos.contains(&addr) => out.push_str(&format!("L{}:\n", addr)),
+                Line::Label(_) => {}
+                Line::Text(text) => {
+                    out.push_str(&text);
+                    out.push('\n');
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn decompile(&self) -> String {
+        let funcs: Vec<String> = self.cfg.functions.keys().map(|&entry| self.function(entry)).collect();
+        funcs.join("\n")
+    }
+}
+
+pub fn decompile(code: &[i64]) -> String {
+    Decompiler::new(code).decompile()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, test_util::aoc_program};
+
+    // outputs twice each of 0..n except 3, for which it outputs 0
+    const DOUBLES: &str = "
+            arb #100
+            in [n]
+        loop: lt [i], [n], [t]
+            jf [t], #done
+            add [i], #0, rb+1
+            add #back, #0, rb+0
+            jt #1, #double
+        back: eq [i], #3, [t]
+            jf [t], #odd
+            out #0
+            jt #1, #next
+        odd: out rb+1
+        next: add [i], #1, [i]
+            jt #1, #loop
+        done: hlt
+        double: arb #2
+            mul rb-1, #2, rb-1
+            arb #-2
+            jt #1, rb+0
+        n: .data 0
+        i: .data 0
+        t: .data 0
+    ";
+
+    #[test]
+    fn test_structure() {
+        let code = assemble(DOUBLES).unwrap().code;
+        assert_eq!(decompile(&code), "\
+fn main() {
+    // stack at 100
+    g55 = input();
+    loop {
+        g57 = (g56 < g55) as i64;
+        if g56 >= g55 { break; }
+        arg1 = g56;
+        f44(arg1);
+        g57 = (g56 == 3) as i64;
+        if g56 == 3 {
+            output(0);
+        } else {
+            output(arg1);
+        }
+        g56 += 1;
+    }
+    halt();
+}
+
+fn f44(a1: i64) {
+    a1 = a1 * 2;
+    return;
+}
+");
+    }
+
+    #[test]
+    fn test_springdroid() {
+        let prog = aoc_program!(21);
+        let text = decompile(&prog.code);
+        // reads the expected char and bails out with a message otherwise
+        assert!(text.contains("fn f1301(a1: i64, a2: i64) {\n    let mut v3: i64;"));
+        assert!(text.contains("        output(mem[g1408]);\n        v2 += 1;\n    }"));
+        assert_eq!(text.matches("goto").count(), 13);
+    }
+
+    #[test]
+    fn test_function_pointer() {
+        let prog = aoc_program!(19);
+        let text = decompile(&prog.code);
+        assert!(text.contains("    g249 = a1;\n"));
+        assert!(text.contains("    (*g249)(arg1, arg2, arg3);\n"));
+    }
+
+    #[test]
+    fn test_extreme_operands() {
+        let (min, max) = (i64::MIN, i64::MAX);
+        let code = [109, min, 21101, 1, min, -5, 1101, 7, min, 30, 204, max, 109, min, 99, 0];
+        assert_eq!(decompile(&code), "\
+fn main() {
+    // stack at -9223372036854775808
+    up5 = 1 - 9223372036854775808;
+    g30 = 7 - 9223372036854775808;
+    output(arg9223372036854775807);
+    rb += -9223372036854775808;
+    halt();
+}
+");
+    }
+}
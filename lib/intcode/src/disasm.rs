@@ -0,0 +1,290 @@
+use std::{collections::{BTreeSet, VecDeque}, fmt};
+
+use crate::int::{decode, Instruction, OpCode, OpParamMode, OpParamType};
+
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Instr { addr: usize, instr: Instruction, words: Vec<i64> },
+    Data { addr: usize, words: Vec<i64> },
+}
+
+impl Item {
+    pub fn addr(&self) -> usize {
+        match self {
+            Item::Instr { addr, .. } | Item::Data { addr, .. } => *addr,
+        }
+    }
+
+    pub fn words(&self) -> &[i64] {
+        match self {
+            Item::Instr { words, .. } | Item::Data { words, .. } => words,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    pub items: Vec<Item>,
+    pub labels: BTreeSet<usize>,
+}
+
+/// Immediate operands holding code addresses as `(param index, address)`:
+/// jump targets and constants stored to `rb+0`, which is how the usual
+/// calling convention pushes the return address before jumping to a function.
+pub fn code_refs(instr: &Instruction, args: &[i64]) -> Vec<(usize, i64)> {
+    use OpParamMode::*;
+    match (instr.code, instr.modes) {
+        (OpCode::JumpIfTrue | OpCode::JumpIfFalse, [_, Immediate, _]) => vec![(1, args[1])],
+        (OpCode::Add | OpCode::Mul, [Immediate, Immediate, Relative]) if args[2] == 0 => {
+            let unit = if instr.code == OpCode::Add { 0 } else { 1 };
+            if args[1] == unit {
+                vec![(0, args[0])]
+            } else if args[0] == unit {
+                vec![(1, args[1])]
+            } else {
+                vec![]
+            }
+        }
+        _ => vec![],
+    }
+}
+
+/// Whether control can fall through to the next instruction.
+pub fn falls_through(instr: &Instruction, args: &[i64]) -> bool {
+    match (instr.code, instr.modes[0]) {
+        (OpCode::Halt, _) => false,
+        (OpCode::JumpIfTrue, OpParamMode::Immediate) => args[0] == 0,
+        (OpCode::JumpIfFalse, OpParamMode::Immediate) => args[0] != 0,
+        _ => true,
+    }
+}
+
+/// Whether a jump can be taken, `false` for everything but jumps.
+pub fn may_jump(instr: &Instruction, args: &[i64]) -> bool {
+    match (instr.code, instr.modes[0]) {
+        (OpCode::JumpIfTrue, OpParamMode::Immediate) => args[0] != 0,
+        (OpCode::JumpIfFalse, OpParamMode::Immediate) => args[0] == 0,
+        (OpCode::JumpIfTrue | OpCode::JumpIfFalse, _) => true,
+        _ => false,
+    }
+}
+
+/// Decodes the instruction at `addr` if it fits in `code` and could execute.
+pub fn decode_at(code: &[i64], addr: usize) -> Option<(Instruction, &[i64])> {
+    let instr = decode(addr, *code.get(addr)?).ok()?;
+    let args = code.get(addr + 1..addr + instr.code.size())?;
+    let immediate_write = instr.params()
+        .any(|p| p == (OpParamType::Write, OpParamMode::Immediate));
+    if immediate_write {
+        None
+    } else {
+        Some((instr, args))
+    }
+}
+
+/// Instruction starts reachable from address 0. Everything else is data,
+/// including words with mode digits the assembler wouldn't write back, so
+/// the listing reassembles to the same code.
+pub fn find_code(code: &[i64]) -> BTreeSet<usize> {
+    let mut starts = BTreeSet::new();
+    let mut queue = VecDeque::from([0usize]);
+    while let Some(addr) = queue.pop_front() {
+        if starts.contains(&addr) {
+            continue;
+        }
+        let Some((instr, args)) = decode_at(code, addr) else {
+            continue;
+        };
+        if instr.encode() != code[addr] {
+            continue;
+        }
+        starts.insert(addr);
+        if falls_through(&instr, args) {
+            queue.push_back(addr + instr.code.size());
+        }
+        let is_jump = matches!(instr.code, OpCode::JumpIfTrue | OpCode::JumpIfFalse);
+        for (_, target) in code_refs(&instr, args) {
+            if is_jump && !may_jump(&instr, args) {
+                continue;
+            }
+            if let Ok(target) = usize::try_from(target) {
+                queue.push_back(target);
+            }
+        }
+    }
+    starts
+}
+
+pub fn disassemble(code: &[i64]) -> Listing {
+    let starts = find_code(code);
+    let mut items = vec![];
+    let mut addr = 0;
+    while addr < code.len() {
+        if starts.contains(&addr) {
+            let (instr, _) = decode_at(code, addr).unwrap();
+            let size = instr.code.size();
+            items.push(Item::Instr { addr, instr, words: code[addr..addr + size].to_vec() });
+            addr += size;
+        } else {
+            match items.last_mut() {
+                Some(Item::Data { words, .. }) if words.len() < DATA_PER_LINE => words.push(code[addr]),
+                _ => items.push(Item::Data { addr, words: vec![code[addr]] }),
+            }
+            addr += 1;
+        }
+    }
+
+    // only label code references that ended up as emitted instructions
+    let emitted: BTreeSet<usize> = items.iter()
+        .filter(|it| matches!(it, Item::Instr { .. }))
+        .map(|it| it.addr())
+        .collect();
+    let labels = items.iter()
+        .filter_map(|it| match it {
+            Item::Instr { instr, words, .. } => Some(code_refs(instr, &words[1..])),
+            Item::Data { .. } => None,
+        })
+        .flatten()
+        .filter_map(|(_, target)| usize::try_from(target).ok())
+        .filter(|target| emitted.contains(target))
+        .collect();
+    Listing { items, labels }
+}
+
+pub fn label_name(addr: usize) -> String {
+    format!("L{}", addr)
+}
+
+pub fn format_operand(mode: OpParamMode, val: i64) -> String {
+    match mode {
+        OpParamMode::Position => format!("[{}]", val),
+        OpParamMode::Immediate => format!("#{}", val),
+        OpParamMode::Relative if val < 0 => format!("rb{}", val),
+        OpParamMode::Relative => format!("rb+{}", val),
+    }
+}
+
+impl Listing {
+    /// Mnemonic and operands of an item, the part that assembles back.
+    pub fn item_text(&self, item: &Item) -> String {
+        match item {
+            Item::Instr { instr, words, .. } => {
+                let args = &words[1..];
+                let refs = code_refs(instr, args);
+                let operands: Vec<String> = instr.modes.iter()
+                    .zip(args)
+                    .enumerate()
+                    .map(|(i, (&mode, &val))| {
+                        let is_label = refs.iter().any(|&(ri, _)| ri == i)
+                            && usize::try_from(val).is_ok_and(|v| self.labels.contains(&v));
+                        if is_label {
+                            format!("#{}", label_name(val as usize))
+                        } else {
+                            format_operand(mode, val)
+                        }
+                    })
+                    .collect();
+                if operands.is_empty() {
+                    instr.code.mnemonic().to_owned()
+                } else {
+                    format!("{} {}", instr.code.mnemonic(), operands.join(", "))
+                }
+            }
+            Item::Data { words, .. } => {
+                let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+                format!(".data {}", words.join(", "))
+            }
+        }
+    }
+
+    /// Plain assembly source without addresses and raw words.
+    pub fn source(&self) -> String {
+        let mut out = String::new();
+        for item in self.items.iter() {
+            if self.labels.contains(&item.addr()) {
+                out.push_str(&label_name(item.addr()));
+                out.push_str(":\n");
+            }
+            out.push_str("    ");
+            out.push_str(&self.item_text(item));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for item in self.items.iter() {
+            if self.labels.contains(&item.addr()) {
+                writeln!(f, "{}:", label_name(item.addr()))?;
+            }
+            let raw: Vec<String> = item.words().iter().map(|w| w.to_string()).collect();
+            writeln!(f, "{:>6}: {:<28} {}", item.addr(), raw.join(","), self.item_text(item))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        // counts down from 3, outputs each value, then jumps back
+        let code = [
+            1101, 3, 0, 20,
+            4, 20,
+            101, -1, 20, 20,
+            1005, 20, 4,
+            99,
+            7, 7,
+        ];
+        let listing = disassemble(&code);
+        assert_eq!(listing.source(), "    add #3, #0, [20]
+L4:
+    out [20]
+    add #-1, [20], [20]
+    jt [20], #L4
+    hlt
+    .data 7, 7
+");
+        assert_eq!(listing.to_string().lines().nth(2).unwrap(),
+            "     4: 4,20                         out [20]");
+    }
+
+    #[test]
+    fn test_relative_and_pointers() {
+        // pushes return address 9 then jumps to a function at 11
+        let code = [
+            109, 20,
+            21101, 9, 0, 0,
+            1105, 1, 11,
+            99,
+            0,
+            204, -1,
+            2105, 1, 0,
+        ];
+        let listing = disassemble(&code);
+        assert_eq!(listing.source(), "    arb #20
+    add #L9, #0, rb+0
+    jt #1, #L11
+L9:
+    hlt
+    .data 0
+L11:
+    out rb-1
+    jt #1, rb+0
+");
+    }
+
+    #[test]
+    fn test_unreachable_is_data() {
+        let listing = disassemble(&[99, 1, 2, 3, 4]);
+        assert_eq!(listing.items.len(), 2);
+        assert_eq!(listing.items[1], Item::Data { addr: 1, words: vec![1, 2, 3, 4] });
+    }
+}
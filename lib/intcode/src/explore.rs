@@ -0,0 +1,386 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+};
+
+use crate::{
+    budget::Budget,
+    int::{Computer, ComputerState, VmError},
+    program::Program,
+    trace::Tracer,
+};
+
+/// Grid position, `y` grows southwards.
+pub type Pos = (i64, i64);
+
+/// Move command, sent as 1 to 4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dir {
+    North,
+    South,
+    West,
+    East,
+}
+
+impl Dir {
+    pub const ALL: [Dir; 4] = [Dir::North, Dir::South, Dir::West, Dir::East];
+
+    pub fn code(self) -> i64 {
+        match self {
+            Dir::North => 1,
+            Dir::South => 2,
+            Dir::West => 3,
+            Dir::East => 4,
+        }
+    }
+
+    pub fn step(self, (x, y): Pos) -> Pos {
+        match self {
+            Dir::North => (x, y - 1),
+            Dir::South => (x, y + 1),
+            Dir::West => (x - 1, y),
+            Dir::East => (x + 1, y),
+        }
+    }
+
+    pub fn turn_left(self) -> Dir {
+        match self {
+            Dir::North => Dir::West,
+            Dir::West => Dir::South,
+            Dir::South => Dir::East,
+            Dir::East => Dir::North,
+        }
+    }
+
+    pub fn turn_right(self) -> Dir {
+        self.turn_left().turn_left().turn_left()
+    }
+
+    /// Direction of a single step from `from` to `to`.
+    pub fn between(from: Pos, to: Pos) -> Option<Dir> {
+        Dir::ALL.into_iter().find(|d| d.step(from) == to)
+    }
+}
+
+/// What the robot reported about a cell: status 0 is a wall, any other
+/// status means it could move there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Wall,
+    Open(i64),
+}
+
+impl Cell {
+    pub fn from_status(status: i64) -> Cell {
+        match status {
+            0 => Cell::Wall,
+            _ => Cell::Open(status),
+        }
+    }
+}
+
+/// Cells found so far, anything else is unknown. Grows in every direction
+/// from wherever the exploration started.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Maze {
+    cells: HashMap<Pos, Cell>,
+}
+
+impl Maze {
+    pub fn get(&self, pos: Pos) -> Option<Cell> {
+        self.cells.get(&pos).copied()
+    }
+
+    pub fn set(&mut self, pos: Pos, cell: Cell) {
+        self.cells.insert(pos, cell);
+    }
+
+    pub fn is_open(&self, pos: Pos) -> bool {
+        matches!(self.get(pos), Some(Cell::Open(_)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (Pos, Cell)> + '_ {
+        self.cells.iter().map(|(&pos, &cell)| (pos, cell))
+    }
+
+    /// Open cells reported with `status`, sorted.
+    pub fn find(&self, status: i64) -> Vec<Pos> {
+        let mut found: Vec<Pos> =
+            self.cells().filter(|&(_, cell)| cell == Cell::Open(status)).map(|(pos, _)| pos).collect();
+        found.sort_unstable();
+        found
+    }
+
+    /// Smallest and largest known coordinates.
+    pub fn bounds(&self) -> Option<(Pos, Pos)> {
+        let xs = self.cells.keys().map(|p| p.0);
+        let ys = self.cells.keys().map(|p| p.1);
+        Some(((xs.clone().min()?, ys.clone().min()?), (xs.max()?, ys.max()?)))
+    }
+
+    /// Open cells next to an unknown one, sorted. Empty once the maze is
+    /// fully mapped.
+    pub fn frontier(&self) -> Vec<Pos> {
+        let mut frontier: Vec<Pos> = self
+            .cells()
+            .filter(|&(pos, cell)| {
+                matches!(cell, Cell::Open(_)) && Dir::ALL.iter().any(|d| self.get(d.step(pos)).is_none())
+            })
+            .map(|(pos, _)| pos)
+            .collect();
+        frontier.sort_unstable();
+        frontier
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.frontier().is_empty()
+    }
+
+    /// Shortest walk through open cells from `from` to the nearest unknown
+    /// cell, the last step going into it.
+    pub fn path_to_unknown(&self, from: Pos) -> Option<Vec<Dir>> {
+        let mut parents: HashMap<Pos, Pos> = HashMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(pos) = queue.pop_front() {
+            for dir in Dir::ALL {
+                let next = dir.step(pos);
+                if parents.contains_key(&next) {
+                    continue;
+                }
+                match self.get(next) {
+                    Some(Cell::Wall) => continue,
+                    Some(Cell::Open(_)) => {
+                        parents.insert(next, pos);
+                        queue.push_back(next);
+                    }
+                    None => {
+                        let mut path = vec![dir];
+                        let mut at = pos;
+                        while at != from {
+                            let parent = parents[&at];
+                            path.push(Dir::between(parent, at).unwrap());
+                            at = parent;
+                        }
+                        path.reverse();
+                        return Some(path);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// `#` for walls, blank for status 1, the digit for other statuses and `.`
+/// for unknown cells, over the known bounds.
+impl fmt::Display for Maze {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(((x0, y0), (x1, y1))) = self.bounds() else {
+            return Ok(());
+        };
+        for y in y0..=y1 {
+            let row: String = (x0..=x1)
+                .map(|x| match self.get((x, y)) {
+                    None => '.',
+                    Some(Cell::Wall) => '#',
+                    Some(Cell::Open(1)) => ' ',
+                    Some(Cell::Open(status)) => u32::try_from(status).ok().and_then(|s| char::from_digit(s, 36)).unwrap_or('?'),
+                })
+                .collect();
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Every cell reachable from the start is known.
+    Mapped,
+    BudgetExhausted,
+    /// The robot halted, or asked for a move without reporting the last one.
+    Unresponsive,
+}
+
+/// Maps an unknown grid through a robot that takes a direction and reports
+/// whether it hit a wall (0) or moved (any other status).
+///
+/// Walking, the default, keeps one robot and walks it to the nearest
+/// unknown cell every time, so `comp` and `pos` end wherever it stopped.
+/// Forking instead keeps a clone of the machine standing on every open cell
+/// still to be looked around, and probes each neighbour from a fresh clone
+/// of it: no walking back, and clones share memory pages until written.
+pub struct Explorer {
+    pub maze: Maze,
+    pub comp: Computer,
+    pub pos: Pos,
+    forks: Option<VecDeque<(Pos, Computer)>>,
+}
+
+impl Explorer {
+    pub fn new(prog: &Program) -> Explorer {
+        Explorer::from_computer(Computer::new(prog))
+    }
+
+    /// The robot starts at (0, 0), known to be open.
+    pub fn from_computer(comp: Computer) -> Explorer {
+        let mut maze = Maze::default();
+        maze.set((0, 0), Cell::Open(1));
+        Explorer { maze, comp, pos: (0, 0), forks: None }
+    }
+
+    pub fn with_forking(mut self) -> Explorer {
+        self.forks = Some(VecDeque::from([(self.pos, self.comp.clone())]));
+        self
+    }
+
+    /// Explores until the maze is mapped, with no step limit.
+    pub fn explore(&mut self) -> Result<Status, VmError> {
+        self.explore_budgeted(&mut Budget::new(u64::MAX))
+    }
+
+    /// Explores spending `budget`. A forking exploration picks up where it
+    /// left off when called again with more budget, a walking one may have
+    /// lost the move it was making.
+    pub fn explore_budgeted(&mut self, budget: &mut Budget) -> Result<Status, VmError> {
+        self.explore_with(budget, &mut Computer::run_budgeted)
+    }
+
+    /// `explore_budgeted` reporting every executed instruction to `tracer`.
+    pub fn explore_traced(&mut self, budget: &mut Budget, tracer: &mut impl Tracer) -> Result<Status, VmError> {
+        self.explore_with(budget, &mut |comp: &mut Computer, budget: &mut Budget| comp.run_budgeted_traced(budget, tracer))
+    }
+
+    fn explore_with(&mut self, budget: &mut Budget, run: &mut impl Run) -> Result<Status, VmError> {
+        if self.forks.is_some() {
+            self.explore_forking(budget, run)
+        } else {
+            self.explore_walking(budget, run)
+        }
+    }
+
+    fn explore_walking(&mut self, budget: &mut Budget, run: &mut impl Run) -> Result<Status, VmError> {
+        while let Some(path) = self.maze.path_to_unknown(self.pos) {
+            for dir in path {
+                let next = dir.step(self.pos);
+                let status = match probe(&mut self.comp, dir, budget, run)? {
+                    Ok(status) => status,
+                    Err(end) => return Ok(end),
+                };
+                self.maze.set(next, Cell::from_status(status));
+                if status == 0 {
+                    break;
+                }
+                self.pos = next;
+            }
+        }
+        Ok(Status::Mapped)
+    }
+
+    fn explore_forking(&mut self, budget: &mut Budget, run: &mut impl Run) -> Result<Status, VmError> {
+        let forks = self.forks.as_mut().unwrap();
+        while let Some((pos, comp)) = forks.pop_front() {
+            for dir in Dir::ALL {
+                let next = dir.step(pos);
+                if self.maze.get(next).is_some() {
+                    continue;
+                }
+                let mut fork = comp.clone();
+                let status = match probe(&mut fork, dir, budget, run)? {
+                    Ok(status) => status,
+                    Err(end) => {
+                        // neighbours not probed yet are still unknown
+                        forks.push_front((pos, comp));
+                        return Ok(end);
+                    }
+                };
+                self.maze.set(next, Cell::from_status(status));
+                if status != 0 {
+                    forks.push_back((next, fork));
+                }
+            }
+        }
+        Ok(Status::Mapped)
+    }
+}
+
+// `Computer::run_budgeted`, traced or not
+trait Run: FnMut(&mut Computer, &mut Budget) -> Result<ComputerState, VmError> {}
+
+impl<F: FnMut(&mut Computer, &mut Budget) -> Result<ComputerState, VmError>> Run for F {}
+
+// sends one move and runs until the robot reports on it
+fn probe(comp: &mut Computer, dir: Dir, budget: &mut Budget, run: &mut impl Run) -> Result<Result<i64, Status>, VmError> {
+    comp.ins.push_back(dir.code());
+    loop {
+        let state = run(comp, budget)?;
+        if let Some(status) = comp.outs.pop_front() {
+            return Ok(Ok(status));
+        }
+        match state {
+            ComputerState::WriteInt => {}
+            ComputerState::BudgetExhausted => return Ok(Err(Status::BudgetExhausted)),
+            ComputerState::ReadInt | ComputerState::Halted => return Ok(Err(Status::Unresponsive)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::aoc_program;
+
+    fn droid() -> Program {
+        aoc_program!(15)
+    }
+
+    #[test]
+    fn test_maze() {
+        let mut maze = Maze::default();
+        maze.set((0, 0), Cell::Open(1));
+        maze.set((1, 0), Cell::Wall);
+        maze.set((0, -1), Cell::Open(2));
+        assert_eq!(maze.bounds(), Some(((0, -1), (1, 0))));
+        assert_eq!(maze.frontier(), [(0, -1), (0, 0)]);
+        assert_eq!(maze.find(2), [(0, -1)]);
+        assert_eq!(maze.path_to_unknown((0, -1)), Some(vec![Dir::North]));
+        assert_eq!(maze.to_string(), "2.\n #\n");
+        assert_eq!(Dir::between((0, 0), (0, 1)), Some(Dir::South));
+    }
+
+    #[test]
+    fn test_walking_and_forking_agree() {
+        let (mut walk_budget, mut fork_budget) = (Budget::new(u64::MAX), Budget::new(u64::MAX));
+        let mut walking = Explorer::new(&droid());
+        assert_eq!(walking.explore_budgeted(&mut walk_budget), Ok(Status::Mapped));
+        let mut forking = Explorer::new(&droid()).with_forking();
+        assert_eq!(forking.explore_budgeted(&mut fork_budget), Ok(Status::Mapped));
+
+        assert!(walking.maze.is_complete());
+        assert_eq!(walking.maze, forking.maze);
+        assert_eq!(walking.maze.find(2), [(16, 12)]);
+        assert!(fork_budget.used() < walk_budget.used());
+    }
+
+    #[test]
+    fn test_forking_resumes() {
+        let mut explorer = Explorer::new(&droid()).with_forking();
+        let mut budget = Budget::new(10_000);
+        assert_eq!(explorer.explore_budgeted(&mut budget), Ok(Status::BudgetExhausted));
+        assert!(!explorer.maze.is_complete());
+        budget.extend(u64::MAX - 10_000);
+        assert_eq!(explorer.explore_budgeted(&mut budget), Ok(Status::Mapped));
+
+        let mut whole = Explorer::new(&droid()).with_forking();
+        assert_eq!(whole.explore(), Ok(Status::Mapped));
+        assert_eq!(explorer.maze, whole.maze);
+    }
+}
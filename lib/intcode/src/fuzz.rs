@@ -0,0 +1,305 @@
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    block::Engine,
+    budget::Budget,
+    int::{Computer, ComputerState, OpCode, OpParamMode, VmError},
+    program::Program,
+};
+
+#[path = "../benches/decode/legacy.rs"]
+mod legacy;
+
+// words of memory the legacy interpreter gets at least, it can't grow
+const LEGACY_MEM: usize = 4096;
+
+/// Everything observable when a run ends.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub end: Result<ComputerState, VmError>,
+    pub outs: Vec<i64>,
+    pub eip: usize,
+    pub esp: usize,
+    pub mem: Vec<i64>,
+}
+
+/// Something wrong with an engine, found by `check`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Finding {
+    Panic { engine: Engine, message: String },
+    Mismatch { interpreter: Box<Outcome>, blocks: Box<Outcome> },
+    /// The string-decoding interpreter from before the rewrite panicked on
+    /// a run it should have been able to follow.
+    LegacyPanic { message: String },
+    LegacyMismatch { interpreter: Box<Outcome>, legacy: Box<Outcome> },
+}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(s) => *s,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("?", |s| s).to_owned(),
+    }
+}
+
+/// Runs `code` with all of `input` queued, past every output, until it
+/// wants more input, halts, fails or has used up `max_steps`. The block
+/// engine gets as many blocks per run instead, at least as many steps.
+/// A panic comes back as a `Finding`.
+pub fn run(code: &[i64], input: &[i64], engine: Engine, max_steps: u64) -> Result<Outcome, Finding> {
+    let prog = Program::from(code.to_vec());
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut comp = Computer::new(&prog).with_engine(engine).with_input(input.iter().copied());
+        let mut budget = Budget::new(max_steps);
+        // outputs take a step each, so this many runs cover the budget
+        let mut end = Ok(ComputerState::WriteInt);
+        for _ in 0..=max_steps {
+            end = match engine {
+                Engine::Interpreter => comp.run_budgeted(&mut budget),
+                Engine::Blocks => comp.run_blocks_for(max_steps),
+            };
+            if end != Ok(ComputerState::WriteInt) {
+                break;
+            }
+        }
+        Outcome { end, outs: comp.outs.into(), eip: comp.eip, esp: comp.esp, mem: comp.mem.to_vec() }
+    }));
+    res.map_err(|payload| Finding::Panic { engine, message: panic_message(payload) })
+}
+
+/// True if the legacy interpreter can follow the interpreter's whole run
+/// on `code`: it ends in a halt or an input wait, stays inside the legacy
+/// memory and never hits what the legacy interpreter got wrong, jumping on
+/// a negative condition and overflowing arithmetic.
+fn legacy_can_follow(code: &[i64], input: &[i64], max_steps: u64) -> bool {
+    let mem_len = code.len().max(LEGACY_MEM) as i64;
+    let mut comp = Computer::new(&Program::from(code.to_vec())).with_input(input.iter().copied());
+    for _ in 0..max_steps {
+        let Ok(op) = comp.fetch() else {
+            return false;
+        };
+        let size = op.code.size();
+        if comp.eip + size > mem_len as usize {
+            return false;
+        }
+        let esp = comp.esp as i64;
+        let in_mem = (1..size).zip(op.param_modes).all(|(i, mode)| {
+            let arg = comp.mem[comp.eip + i];
+            match mode {
+                OpParamMode::Position => (0..mem_len).contains(&arg),
+                OpParamMode::Relative => esp.checked_add(arg).is_some_and(|addr| (0..mem_len).contains(&addr)),
+                OpParamMode::Immediate => true,
+            }
+        });
+        let legacy_bug = match op.code {
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => op.params[0] < 0,
+            OpCode::Add => op.params[0].checked_add(op.params[1]).is_none(),
+            OpCode::Mul => op.params[0].checked_mul(op.params[1]).is_none(),
+            _ => false,
+        };
+        if !in_mem || legacy_bug {
+            return false;
+        }
+        match comp.execute(op) {
+            Ok(Some(ComputerState::Halted | ComputerState::ReadInt)) => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    false
+}
+
+/// `run` on the legacy interpreter, memory padded to `LEGACY_MEM` words.
+fn run_legacy(code: &[i64], input: &[i64], max_steps: u64) -> Result<Outcome, Finding> {
+    let mut mem = code.to_vec();
+    mem.resize(code.len().max(LEGACY_MEM), 0);
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut comp = legacy::Computer {
+            steps: 0,
+            // the read that finds no input counts too
+            max_steps: max_steps + 1,
+            eip: 0,
+            esp: 0,
+            mem,
+            ins: input.iter().copied().collect(),
+            outs: VecDeque::new(),
+        };
+        let end = loop {
+            match comp.run() {
+                Some(legacy::ComputerState::WriteInt) => {}
+                Some(legacy::ComputerState::ReadInt) => break Ok(ComputerState::ReadInt),
+                Some(legacy::ComputerState::Halted) => break Ok(ComputerState::Halted),
+                None => break Ok(ComputerState::BudgetExhausted),
+            }
+        };
+        Outcome { end, outs: comp.outs.into(), eip: comp.eip, esp: comp.esp, mem: comp.mem }
+    }));
+    res.map_err(|payload| Finding::LegacyPanic { message: panic_message(payload) })
+}
+
+/// Runs `code` on both engines and compares how they end, and on the
+/// legacy interpreter too when `legacy_can_follow`. `None` if the
+/// interpreter didn't end within `max_steps`, there's nothing to compare.
+pub fn check(code: &[i64], input: &[i64], max_steps: u64) -> Result<Option<Outcome>, Finding> {
+    let interpreter = run(code, input, Engine::Interpreter, max_steps)?;
+    if interpreter.end == Ok(ComputerState::BudgetExhausted) {
+        return Ok(None);
+    }
+    let blocks = run(code, input, Engine::Blocks, max_steps)?;
+    if blocks != interpreter {
+        return Err(Finding::Mismatch { interpreter: Box::new(interpreter), blocks: Box::new(blocks) });
+    }
+    if legacy_can_follow(code, input, max_steps) {
+        let legacy = run_legacy(code, input, max_steps)?;
+        let mut padded = interpreter.clone();
+        padded.mem.resize(legacy.mem.len(), 0);
+        if legacy != padded {
+            return Err(Finding::LegacyMismatch { interpreter: Box::new(padded), legacy: Box::new(legacy) });
+        }
+    }
+    Ok(Some(interpreter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{block::MAX_BLOCK_OPS, int::OpParamType};
+    use proptest::prelude::*;
+
+    const MAX_STEPS: u64 = 1000;
+
+    // mostly well-formed instructions so runs get somewhere, with the odd
+    // bad opcode or mode and extreme operands mixed in
+    fn instruction() -> impl Strategy<Value = Vec<i64>> {
+        let opcode = prop_oneof![
+            20 => prop::sample::select(OpCode::ALL.to_vec()).prop_map(|code| code as i64),
+            1 => -5i64..120,
+        ];
+        let mode = prop_oneof![10 => 0i64..3, 1 => 3i64..10];
+        let operand = prop_oneof![
+            10 => -3i64..64,
+            1 => any::<i64>(),
+            1 => Just(i64::MAX),
+            1 => Just(i64::MIN),
+        ];
+        (opcode, prop::array::uniform3(mode), prop::array::uniform3(operand)).prop_map(|(code, modes, args)| words(code, modes, args))
+    }
+
+    // valid instructions with small operands, the kind the legacy
+    // interpreter can follow
+    fn tame_instruction() -> impl Strategy<Value = Vec<i64>> {
+        let opcode = prop::sample::select(OpCode::ALL.to_vec());
+        (opcode, prop::array::uniform3(0i64..3), prop::array::uniform3(0i64..64)).prop_map(|(code, mut modes, args)| {
+            for (mode, ty) in modes.iter_mut().zip(code.param_types()) {
+                if *ty == OpParamType::Write && *mode == 1 {
+                    *mode = 0;
+                }
+            }
+            words(code as i64, modes, args)
+        })
+    }
+
+    fn words(code: i64, modes: [i64; 3], args: [i64; 3]) -> Vec<i64> {
+        let word = code + 100 * modes[0] + 1000 * modes[1] + 10000 * modes[2];
+        let size = OpCode::ALL.iter().find(|c| **c as i64 == code).map_or(1, |c| c.size());
+        std::iter::once(word).chain(args).take(size).collect()
+    }
+
+    // arithmetic only, with writes landing anywhere in a program so
+    // instructions further down the same block get patched
+    fn straight_op() -> impl Strategy<Value = Vec<i64>> {
+        let code = prop::sample::select(vec![OpCode::Add, OpCode::Mul, OpCode::CmpLess, OpCode::CmpEquals]);
+        (code, prop::array::uniform2(0i64..2), prop::array::uniform2(-3i64..64), 0i64..512)
+            .prop_map(|(code, modes, args, dst)| vec![code as i64 + 100 * modes[0] + 1000 * modes[1], args[0], args[1], dst])
+    }
+
+    #[derive(Debug, Clone)]
+    enum Piece {
+        Words(Vec<i64>),
+        /// `jt [cond], #start` of this or an earlier piece.
+        BackJump { cond: i64, target: prop::sample::Index },
+    }
+
+    // runs longer than a compiled block, looping back over them
+    fn program_of(instruction: impl Strategy<Value = Vec<i64>>) -> impl Strategy<Value = Vec<i64>> {
+        let straight_run = prop::collection::vec(straight_op(), MAX_BLOCK_OPS + 1..2 * MAX_BLOCK_OPS);
+        let piece = prop_oneof![
+            8 => instruction.prop_map(Piece::Words),
+            1 => straight_run.prop_map(|ops| Piece::Words(ops.concat())),
+            2 => (0i64..64, any::<prop::sample::Index>()).prop_map(|(cond, target)| Piece::BackJump { cond, target }),
+        ];
+        prop::collection::vec(piece, 1..24).prop_map(|pieces| {
+            let (mut code, mut starts) = (vec![], vec![]);
+            for piece in pieces {
+                starts.push(code.len());
+                match piece {
+                    Piece::Words(words) => code.extend(words),
+                    Piece::BackJump { cond, target } => {
+                        code.extend([1005, cond, starts[target.index(starts.len())] as i64]);
+                    }
+                }
+            }
+            code
+        })
+    }
+
+    fn input() -> impl Strategy<Value = Vec<i64>> {
+        prop::collection::vec(prop_oneof![10 => -10i64..100, 1 => any::<i64>()], 0..4)
+    }
+
+    // minimized findings, proptest-regressions/fuzz.txt replays them too
+    #[test]
+    fn test_regressions() {
+        // the add overflowed, panicking in debug builds
+        let end = check(&[1101, i64::MAX, 1, 0], &[], MAX_STEPS).unwrap().unwrap();
+        assert_eq!(end.mem[0], i64::MIN);
+        assert_eq!(end.end, Err(VmError::UnknownOpCode { eip: 4, code: 0 }));
+
+        // jumping far past the program made the block cache grow up to the target
+        let far = 8371466570892542105;
+        let end = check(&[far, 1], &[], MAX_STEPS).unwrap().unwrap();
+        assert_eq!(end.end, Err(VmError::OutOfMemory { eip: far as usize, addr: far }));
+    }
+
+    #[test]
+    fn test_legacy_follows() {
+        // counts down from 3, every output checked against the legacy run
+        let countdown = [1101, 3, 0, 14, 4, 14, 101, -1, 14, 14, 1005, 14, 4, 99, 0];
+        assert!(legacy_can_follow(&countdown, &[], MAX_STEPS));
+        assert_eq!(check(&countdown, &[], MAX_STEPS).unwrap().unwrap().outs, [3, 2, 1]);
+        assert_eq!(run_legacy(&countdown, &[], MAX_STEPS).unwrap().outs, [3, 2, 1]);
+
+        // the legacy jt only jumps on positive conditions
+        let negative_jump = [1105, -1, 4, 99, 99];
+        assert!(!legacy_can_follow(&negative_jump, &[], MAX_STEPS));
+        assert_eq!(check(&negative_jump, &[], MAX_STEPS).unwrap().unwrap().eip, 4);
+        assert_eq!(run_legacy(&negative_jump, &[], MAX_STEPS).unwrap().eip, 3);
+
+        // nor does it get far past its memory or stop looping by itself
+        assert!(!legacy_can_follow(&[3, 100_000, 99], &[1], MAX_STEPS));
+        assert!(matches!(run_legacy(&[3, 100_000, 99], &[1], MAX_STEPS), Err(Finding::LegacyPanic { .. })));
+        assert!(!legacy_can_follow(&[1105, 1, 0], &[], MAX_STEPS));
+        assert_eq!(run_legacy(&[1105, 1, 0], &[], MAX_STEPS).unwrap().end, Ok(ComputerState::BudgetExhausted));
+    }
+
+    // PROPTEST_CASES=100000 for a longer fuzzing session
+    proptest! {
+        #[test]
+        fn prop_engines_agree(code in program_of(instruction()), input in input()) {
+            prop_assert_eq!(check(&code, &input, MAX_STEPS).map(|_| ()), Ok(()));
+        }
+
+        #[test]
+        fn prop_legacy_agrees(code in program_of(tame_instruction()), input in input()) {
+            let code = [code, vec![99]].concat();
+            prop_assert_eq!(check(&code, &input, MAX_STEPS).map(|_| ()), Ok(()));
+        }
+
+        #[test]
+        fn prop_any_words_never_panic(code in prop::collection::vec(any::<i64>(), 1..16), input in input()) {
+            prop_assert_eq!(check(&code, &input, MAX_STEPS).map(|_| ()), Ok(()));
+        }
+    }
+}
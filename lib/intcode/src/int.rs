@@ -0,0 +1,698 @@
+use std::{collections::VecDeque, fmt};
+
+use crate::{block::BlockCache, mem::Memory, program::Program};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpCode {
+    Add = 1,
+    Mul = 2,
+    Read = 3,
+    Write = 4,
+    JumpIfTrue = 5,
+    JumpIfFalse = 6,
+    CmpLess = 7,
+    CmpEquals = 8,
+    SpAdd = 9,
+    Halt = 99,
+}
+
+impl OpCode {
+    pub const ALL: [OpCode; 10] = [
+        OpCode::Add,
+        OpCode::Mul,
+        OpCode::Read,
+        OpCode::Write,
+        OpCode::JumpIfTrue,
+        OpCode::JumpIfFalse,
+        OpCode::CmpLess,
+        OpCode::CmpEquals,
+        OpCode::SpAdd,
+        OpCode::Halt,
+    ];
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            OpCode::Add => "add",
+            OpCode::Mul => "mul",
+            OpCode::Read => "in",
+            OpCode::Write => "out",
+            OpCode::JumpIfTrue => "jt",
+            OpCode::JumpIfFalse => "jf",
+            OpCode::CmpLess => "lt",
+            OpCode::CmpEquals => "eq",
+            OpCode::SpAdd => "arb",
+            OpCode::Halt => "hlt",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<OpCode> {
+        OpCode::ALL.into_iter().find(|code| code.mnemonic() == mnemonic)
+    }
+
+    /// Number of memory words taken by the instruction including params.
+    pub fn size(&self) -> usize {
+        1 + self.param_types().len()
+    }
+
+    pub fn param_types(&self) -> &'static [OpParamType] {
+        type PT = OpParamType;
+        match self {
+            OpCode::Add => &[PT::Read, PT::Read, PT::Write],
+            OpCode::Mul => &[PT::Read, PT::Read, PT::Write],
+            OpCode::Read => &[PT::Write],
+            OpCode::Write => &[PT::Read],
+            OpCode::JumpIfTrue => &[PT::Read, PT::Read],
+            OpCode::JumpIfFalse => &[PT::Read, PT::Read],
+            OpCode::CmpLess => &[PT::Read, PT::Read, PT::Write],
+            OpCode::CmpEquals => &[PT::Read, PT::Read, PT::Write],
+            OpCode::SpAdd => &[PT::Read],
+            OpCode::Halt => &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpParamMode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+impl OpParamMode {
+    /// Mode digit as encoded in an instruction word.
+    pub fn digit(&self) -> i64 {
+        match self {
+            OpParamMode::Position => 0,
+            OpParamMode::Immediate => 1,
+            OpParamMode::Relative => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpParamType {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VmError {
+    UnknownOpCode { eip: usize, code: i64 },
+    InvalidMode { eip: usize, mode: i64 },
+    ImmediateWrite { eip: usize },
+    NegativeAddress { eip: usize, addr: i64 },
+    OutOfMemory { eip: usize, addr: i64 },
+    /// Back in a state it was in earlier without doing any I/O in between.
+    InfiniteLoop { eip: usize },
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::UnknownOpCode { eip, code } => write!(f, "[{}] unknown opcode {}", eip, code),
+            VmError::InvalidMode { eip, mode } => write!(f, "[{}] invalid param mode {}", eip, mode),
+            VmError::ImmediateWrite { eip } => write!(f, "[{}] write param in immediate mode", eip),
+            VmError::NegativeAddress { eip, addr } => write!(f, "[{}] negative address {}", eip, addr),
+            VmError::OutOfMemory { eip, addr } => write!(f, "[{}] address {} out of memory", eip, addr),
+            VmError::InfiniteLoop { eip } => write!(f, "[{}] infinite loop", eip),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+/// Decoded instruction word, modes past the opcode's param count are `Position`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub code: OpCode,
+    pub modes: [OpParamMode; 3],
+}
+
+impl Instruction {
+    /// Instruction word, the inverse of `decode`.
+    pub fn encode(&self) -> i64 {
+        self.modes[..self.code.param_types().len()].iter()
+            .zip(MODE_DIVS)
+            .fold(self.code as i64, |word, (mode, div)| word + mode.digit() * div)
+    }
+
+    pub fn params(&self) -> impl Iterator<Item = (OpParamType, OpParamMode)> + '_ {
+        self.code.param_types().iter().copied().zip(self.modes)
+    }
+}
+
+/// Instruction at `eip` with read params resolved to values and write params
+/// resolved to the addresses they store to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Operation {
+    pub code: OpCode,
+    pub param_modes: [OpParamMode; 3],
+    pub params: [i64; 3],
+}
+
+impl Operation {
+    /// Address the instruction stores to, write params always come last.
+    pub fn write_addr(&self) -> Option<i64> {
+        let types = self.code.param_types();
+        match types.last() {
+            Some(OpParamType::Write) => Some(self.params[types.len() - 1]),
+            _ => None,
+        }
+    }
+}
+
+// opcode lookup by the two lowest decimal digits of an instruction
+const OP_TABLE: [Option<OpCode>; 100] = {
+    let mut table = [None; 100];
+    table[1] = Some(OpCode::Add);
+    table[2] = Some(OpCode::Mul);
+    table[3] = Some(OpCode::Read);
+    table[4] = Some(OpCode::Write);
+    table[5] = Some(OpCode::JumpIfTrue);
+    table[6] = Some(OpCode::JumpIfFalse);
+    table[7] = Some(OpCode::CmpLess);
+    table[8] = Some(OpCode::CmpEquals);
+    table[9] = Some(OpCode::SpAdd);
+    table[99] = Some(OpCode::Halt);
+    table
+};
+
+// divisors selecting the mode digit of the first, second and third param
+const MODE_DIVS: [i64; 3] = [100, 1000, 10000];
+
+#[inline]
+pub fn decode(eip: usize, ins: i64) -> Result<Instruction, VmError> {
+    let code = usize::try_from(ins % 100).ok()
+        .and_then(|i| OP_TABLE[i])
+        .ok_or(VmError::UnknownOpCode { eip, code: ins })?;
+
+    let mut modes = [OpParamMode::Position; 3];
+    for (i, div) in MODE_DIVS[..code.param_types().len()].iter().enumerate() {
+        modes[i] = match ins / div % 10 {
+            0 => OpParamMode::Position,
+            1 => OpParamMode::Immediate,
+            2 => OpParamMode::Relative,
+            mode => return Err(VmError::InvalidMode { eip, mode }),
+        };
+    }
+    Ok(Instruction { code, modes })
+}
+
+#[inline]
+fn parse_opcode(cmp: &Computer, ins: i64, op: &mut Operation) -> Result<(), VmError> {
+    let instr = decode(cmp.eip, ins)?;
+    op.code = instr.code;
+    op.param_modes = instr.modes;
+    Ok(())
+}
+
+#[inline]
+fn parse_params(cmp: &Computer, op: &mut Operation) -> Result<(), VmError> {
+    for (i, ptype) in op.code.param_types().iter().enumerate() {
+        let val = cmp.load(cmp.eip as i64 + 1 + i as i64)?;
+        op.params[i] = match ptype {
+            OpParamType::Write => match op.param_modes[i] {
+                OpParamMode::Position => val,
+                OpParamMode::Immediate => return Err(VmError::ImmediateWrite { eip: cmp.eip }),
+                OpParamMode::Relative => (cmp.esp as i64).wrapping_add(val),
+            },
+            OpParamType::Read => match op.param_modes[i] {
+                OpParamMode::Position => cmp.load(val)?,
+                OpParamMode::Immediate => val,
+                OpParamMode::Relative => cmp.load((cmp.esp as i64).wrapping_add(val))?,
+            },
+        };
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct Computer {
+    pub eip: usize,
+    pub esp: usize,
+    pub mem: Memory,
+    pub ins: VecDeque<i64>,
+    pub outs: VecDeque<i64>,
+    // compiled code when running on the block engine
+    pub(crate) blocks: Option<BlockCache>,
+}
+
+#[repr(i64)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComputerState {
+    ReadInt,
+    WriteInt,
+    Halted,
+    /// Ran out of steps, only from the budgeted runs. Running again resumes.
+    BudgetExhausted,
+}
+
+impl Computer {
+    pub fn new(prog: &Program) -> Computer {
+        Computer {
+            eip: 0,
+            esp: 0,
+            mem: Memory::from(prog.code.as_slice()),
+            ins: VecDeque::new(),
+            outs: VecDeque::new(),
+            blocks: None,
+        }
+    }
+
+    pub fn with_input(mut self, ins: impl IntoIterator<Item = i64>) -> Computer {
+        self.ins.extend(ins);
+        self
+    }
+
+    /// Overwrites a memory cell before the run, like inserting coins in aoc13.
+    pub fn with_patch(mut self, addr: usize, val: i64) -> Computer {
+        self.mem[addr] = val;
+        if let Some(cache) = &mut self.blocks {
+            cache.invalidate(addr);
+        }
+        self
+    }
+
+    pub fn with_mem_limit(mut self, limit: usize) -> Computer {
+        self.mem.set_limit(limit);
+        self
+    }
+
+    #[inline]
+    fn addr(&self, addr: i64) -> Result<usize, VmError> {
+        let index = usize::try_from(addr)
+            .map_err(|_| VmError::NegativeAddress { eip: self.eip, addr })?;
+        if index < self.mem.limit() {
+            Ok(index)
+        } else {
+            Err(VmError::OutOfMemory { eip: self.eip, addr })
+        }
+    }
+
+    #[inline]
+    pub(crate) fn load(&self, addr: i64) -> Result<i64, VmError> {
+        Ok(self.mem.get(self.addr(addr)?))
+    }
+
+    #[inline]
+    pub(crate) fn store(&mut self, addr: i64, val: i64) -> Result<(), VmError> {
+        self.store_code(addr, val).map(|_| ())
+    }
+
+    // `store` dropping the compiled blocks the write hits, true if there
+    // were any
+    #[inline]
+    pub(crate) fn store_code(&mut self, addr: i64, val: i64) -> Result<bool, VmError> {
+        let index = self.addr(addr)?;
+        self.mem.set(index, val)
+            .map_err(|_| VmError::OutOfMemory { eip: self.eip, addr })?;
+        Ok(self.blocks.as_mut().is_some_and(|cache| cache.invalidate(index)))
+    }
+
+    /// Decodes the instruction at `eip` without executing it.
+    #[inline]
+    pub fn fetch(&self) -> Result<Operation, VmError> {
+        let mut op = Operation {
+            code: OpCode::Halt,
+            param_modes: [OpParamMode::Position; 3],
+            params: [0; 3],
+        };
+        let eix = self.load(self.eip as i64)?;
+        parse_opcode(self, eix, &mut op)?;
+        parse_params(self, &mut op)?;
+        Ok(op)
+    }
+
+    /// Executes a single instruction. Returns the state if the machine stops
+    /// on it: waiting for input, right after an output or halted.
+    pub fn step(&mut self) -> Result<Option<ComputerState>, VmError> {
+        let op = self.fetch()?;
+        self.execute(op)
+    }
+
+    // `step` for an instruction already fetched at `eip`
+    #[inline]
+    pub(crate) fn execute(&mut self, op: Operation) -> Result<Option<ComputerState>, VmError> {
+        let [eax, ebx, edi] = op.params;
+        match op.code {
+            OpCode::Add | OpCode::Mul => {
+                let res = if op.code == OpCode::Add {
+                    eax.wrapping_add(ebx)
+                } else {
+                    eax.wrapping_mul(ebx)
+                };
+                self.store(edi, res)?;
+                self.eip += 4;
+            }
+            OpCode::Read => {
+                if let Some(val) = self.ins.pop_front() {
+                    self.store(op.params[0], val)?;
+                    self.eip += 2;
+                } else {
+                    return Ok(Some(ComputerState::ReadInt));
+                }
+            }
+            OpCode::Write => {
+                self.outs.push_back(eax);
+                self.eip += 2;
+                return Ok(Some(ComputerState::WriteInt));
+            }
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse => {
+                let flag = match op.code {
+                    OpCode::JumpIfTrue => eax != 0,
+                    OpCode::JumpIfFalse => eax == 0,
+                    _ => false,
+                };
+                if flag {
+                    self.eip = usize::try_from(ebx)
+                        .map_err(|_| VmError::NegativeAddress { eip: self.eip, addr: ebx })?;
+                } else {
+                    self.eip += 3;
+                }
+            }
+            OpCode::CmpLess => {
+                self.store(edi, if eax < ebx { 1 } else { 0 })?;
+                self.eip += 4;
+            }
+            OpCode::CmpEquals => {
+                self.store(edi, if eax == ebx { 1 } else { 0 })?;
+                self.eip += 4;
+            }
+            OpCode::SpAdd => {
+                let esp = (self.esp as i64).wrapping_add(eax);
+                self.esp = usize::try_from(esp)
+                    .map_err(|_| VmError::NegativeAddress { eip: self.eip, addr: esp })?;
+                self.eip += 2;
+            }
+            OpCode::Halt => return Ok(Some(ComputerState::Halted)),
+        }
+        Ok(None)
+    }
+
+    /// Runs until the machine waits for input, writes or halts.
+    pub fn run(&mut self) -> Result<ComputerState, VmError> {
+        if self.blocks.is_some() {
+            return self.run_blocks();
+        }
+        // Same as looping on `step`, but decodes into one reused operation.
+        let mut op = Operation {
+            code: OpCode::Halt,
+            param_modes: [OpParamMode::Position; 3],
+            params: [0; 3],
+        };
+        loop {
+            let eix = self.load(self.eip as i64)?;
+            parse_opcode(self, eix, &mut op)?;
+            parse_params(self, &mut op)?;
+            if let Some(state) = self.execute(op)? {
+                return Ok(state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{aoc_program, boost};
+
+    fn computer(prog: &[i64]) -> Computer {
+        Computer::new(&Program::from(prog))
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut comp = computer(&[1101, 1, 1, 5, 42, 0]);
+        assert_eq!(comp.run(), Err(VmError::UnknownOpCode { eip: 4, code: 42 }));
+    }
+
+    #[test]
+    fn test_invalid_mode() {
+        let mut comp = computer(&[104, 7, 304, 1, 99]);
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.run(), Err(VmError::InvalidMode { eip: 2, mode: 3 }));
+    }
+
+    #[test]
+    fn test_immediate_write() {
+        let mut comp = computer(&[11101, 1, 1, 0, 99]);
+        assert_eq!(comp.run(), Err(VmError::ImmediateWrite { eip: 0 }));
+    }
+
+    #[test]
+    fn test_negative_address() {
+        let mut comp = computer(&[4, -1, 99]);
+        assert_eq!(comp.run(), Err(VmError::NegativeAddress { eip: 0, addr: -1 }));
+        let mut comp = computer(&[109, -3, 99]);
+        assert_eq!(comp.run(), Err(VmError::NegativeAddress { eip: 0, addr: -3 }));
+    }
+
+    #[test]
+    fn test_out_of_memory() {
+        let mut comp = computer(&[1101, 1, 1, 100, 99]).with_mem_limit(100);
+        assert_eq!(comp.run(), Err(VmError::OutOfMemory { eip: 0, addr: 100 }));
+    }
+
+    #[test]
+    fn test_memory_grows() {
+        let mut comp = computer(&[1101, 1, 1, 9000, 4, 9000, 4, 9001, 99]);
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.outs, [2, 0]);
+        assert_eq!(comp.mem.len(), 9001);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut comp = computer(&[4, 0, 3, 0, 4, 0, 99]).with_patch(0, 104).with_input([7]);
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.outs, [0, 7]);
+    }
+
+    #[test]
+    fn test_step() {
+        let mut comp = computer(&[1101, 2, 3, 7, 4, 7, 99, 0]);
+        assert_eq!(comp.fetch().unwrap().write_addr(), Some(7));
+        assert_eq!(comp.step(), Ok(None));
+        assert_eq!((comp.eip, comp.mem[7]), (4, 5));
+        assert_eq!(comp.fetch().unwrap().write_addr(), None);
+        assert_eq!(comp.step(), Ok(Some(ComputerState::WriteInt)));
+        assert_eq!(comp.step(), Ok(Some(ComputerState::Halted)));
+        assert_eq!(comp.eip, 6);
+    }
+
+    #[test]
+    fn test_read_waits_for_input() {
+        let mut comp = computer(&[3, 5, 4, 5, 99, 0]);
+        assert_eq!(comp.run(), Ok(ComputerState::ReadInt));
+        comp.ins.push_back(13);
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.outs.pop_front(), Some(13));
+        assert_eq!(comp.run(), Ok(ComputerState::Halted));
+    }
+
+    // conformance, every opcode and mode plus the published example programs
+
+    // runs to the end feeding `input`, returns everything written
+    fn outputs(prog: &[i64], input: &[i64]) -> Vec<i64> {
+        let mut comp = computer(prog).with_input(input.iter().copied());
+        while comp.run() == Ok(ComputerState::WriteInt) {}
+        assert_eq!(comp.run(), Ok(ComputerState::Halted));
+        comp.outs.into()
+    }
+
+    const ESP: i64 = 100;
+    const MODES: [OpParamMode; 3] = [OpParamMode::Position, OpParamMode::Immediate, OpParamMode::Relative];
+
+    // param reading `val` in `mode`, storing it at `addr` when not immediate
+    fn read_param(mem: &mut [i64], mode: OpParamMode, addr: i64, val: i64) -> i64 {
+        match mode {
+            OpParamMode::Immediate => return val,
+            _ => mem[addr as usize] = val,
+        }
+        if mode == OpParamMode::Relative { addr - ESP } else { addr }
+    }
+
+    fn write_param(mode: OpParamMode, addr: i64) -> i64 {
+        if mode == OpParamMode::Relative { addr - ESP } else { addr }
+    }
+
+    // sets esp to 100, runs `code a b -> [60]` and outputs [60]
+    fn binary_op(code: OpCode, modes: [OpParamMode; 3], a: i64, b: i64) -> i64 {
+        let mut mem = vec![0; 200];
+        let ins = Instruction { code, modes }.encode();
+        let head = [
+            109, ESP,
+            ins, read_param(&mut mem, modes[0], 150, a), read_param(&mut mem, modes[1], 151, b), write_param(modes[2], 160),
+            4, 160,
+            99,
+        ];
+        mem[..head.len()].copy_from_slice(&head);
+        let outs = outputs(&mem, &[]);
+        assert_eq!(outs.len(), 1);
+        outs[0]
+    }
+
+    #[test]
+    fn test_arithmetic_and_compare_modes() {
+        let cases = [(3, 4), (-7, 5), (0, 0), (12, 12), (-2, -3)];
+        for m1 in MODES {
+            for m2 in MODES {
+                for m3 in [OpParamMode::Position, OpParamMode::Relative] {
+                    let modes = [m1, m2, m3];
+                    for (a, b) in cases {
+                        assert_eq!(binary_op(OpCode::Add, modes, a, b), a + b, "add {:?}", modes);
+                        assert_eq!(binary_op(OpCode::Mul, modes, a, b), a * b, "mul {:?}", modes);
+                        assert_eq!(binary_op(OpCode::CmpLess, modes, a, b), i64::from(a < b), "lt {:?}", modes);
+                        assert_eq!(binary_op(OpCode::CmpEquals, modes, a, b), i64::from(a == b), "eq {:?}", modes);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_jump_modes() {
+        for code in [OpCode::JumpIfTrue, OpCode::JumpIfFalse] {
+            for m1 in MODES {
+                for m2 in MODES {
+                    for flag in [0, 1, -1, 99] {
+                        // jumps to 20 which writes 1, falls through to write 0
+                        let mut mem = vec![0; 200];
+                        let ins = Instruction { code, modes: [m1, m2, OpParamMode::Position] }.encode();
+                        let head = [
+                            109, ESP,
+                            ins, read_param(&mut mem, m1, 150, flag), read_param(&mut mem, m2, 151, 20),
+                            104, 0, 99,
+                        ];
+                        mem[..head.len()].copy_from_slice(&head);
+                        mem[20..23].copy_from_slice(&[104, 1, 99]);
+                        let jumps = (code == OpCode::JumpIfTrue) == (flag != 0);
+                        assert_eq!(outputs(&mem, &[]), [i64::from(jumps)], "{:?} {:?} {:?} {}", code, m1, m2, flag);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_io_modes() {
+        // in [20], in rel 16, then out [21], rel 15 and immediate
+        let prog = [109, 5, 3, 20, 203, 16, 4, 21, 204, 15, 104, 7, 99];
+        assert_eq!(outputs(&prog, &[8, 9]), [9, 8, 7]);
+        let mut comp = computer(&[1103, 0, 99]).with_input([1]);
+        assert_eq!(comp.run(), Err(VmError::ImmediateWrite { eip: 0 }));
+    }
+
+    #[test]
+    fn test_relative_base() {
+        // arb in every mode, relative reads and writes follow it
+        let prog = [
+            109, 10,      // esp 10
+            209, 20,      // esp += [30] = 5
+            9, 31,        // esp += [31] = -3
+            22201, 8, 9, 15, // [27] = [20] + [21]
+            4, 27,
+            99,
+            0, 0, 0, 0, 0, 0, 0,
+            40, 2, 0, 0, 0, 0, 0, 0, 0, 0,
+            5, -3,
+        ];
+        let mut comp = computer(&prog);
+        assert_eq!(comp.run(), Ok(ComputerState::WriteInt));
+        assert_eq!(comp.esp, 12);
+        assert_eq!(comp.outs, [42]);
+        // 2019 day 9 notes: esp 2000, arb 19, value at 1985
+        let mut mem = vec![0; 2000];
+        mem[..7].copy_from_slice(&[109, 2000, 109, 19, 204, -34, 99]);
+        mem[1985] = 77;
+        assert_eq!(outputs(&mem, &[]), [77]);
+    }
+
+    #[test]
+    fn test_large_numbers() {
+        let outs = outputs(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0], &[]);
+        assert_eq!(outs, [1219070632396864]);
+        assert_eq!(outs[0].to_string().len(), 16);
+        assert_eq!(outputs(&[104, 1125899906842624, 99], &[]), [1125899906842624]);
+        assert_eq!(outputs(&[1101, i64::MAX - 1, 1, 7, 4, 7, 99, 0], &[]), [i64::MAX]);
+    }
+
+    #[test]
+    fn test_quine() {
+        let quine = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+        assert_eq!(outputs(&quine, &[]), quine);
+    }
+
+    #[test]
+    fn test_self_modifying() {
+        // 2019 day 2 examples, the result ends up in memory
+        let cases: [(&[i64], &[i64]); 5] = [
+            (&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], &[3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50]),
+            (&[1, 0, 0, 0, 99], &[2, 0, 0, 0, 99]),
+            (&[2, 3, 0, 3, 99], &[2, 3, 0, 6, 99]),
+            (&[2, 4, 4, 5, 99, 0], &[2, 4, 4, 5, 99, 9801]),
+            (&[1, 1, 1, 4, 99, 5, 6, 0, 99], &[30, 1, 1, 4, 2, 5, 6, 0, 99]),
+        ];
+        for (prog, mem) in cases {
+            let mut comp = computer(prog);
+            assert_eq!(comp.run(), Ok(ComputerState::Halted));
+            assert_eq!(comp.mem.to_vec(), mem);
+        }
+        // 2019 day 5, the instruction rewrites its own halt
+        for prog in [[1002, 4, 3, 4, 33], [1101, 100, -1, 4, 0]] {
+            let mut comp = computer(&prog);
+            assert_eq!(comp.run(), Ok(ComputerState::Halted));
+            assert_eq!(comp.mem[4], 99);
+        }
+    }
+
+    #[test]
+    fn test_comparison_examples() {
+        let eq8_pos = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let lt8_pos = [3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+        let eq8_imm = [3, 3, 1108, -1, 8, 3, 4, 3, 99];
+        let lt8_imm = [3, 3, 1107, -1, 8, 3, 4, 3, 99];
+        for input in [-8, 0, 7, 8, 9] {
+            assert_eq!(outputs(&eq8_pos, &[input]), [i64::from(input == 8)]);
+            assert_eq!(outputs(&lt8_pos, &[input]), [i64::from(input < 8)]);
+            assert_eq!(outputs(&eq8_imm, &[input]), [i64::from(input == 8)]);
+            assert_eq!(outputs(&lt8_imm, &[input]), [i64::from(input < 8)]);
+        }
+    }
+
+    #[test]
+    fn test_jump_examples() {
+        let nonzero_pos = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        let nonzero_imm = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+        let cmp8 = [
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31,
+            1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104,
+            999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+        ];
+        for input in [-5, 0, 1, 7, 8, 9, 100] {
+            assert_eq!(outputs(&nonzero_pos, &[input]), [i64::from(input != 0)]);
+            assert_eq!(outputs(&nonzero_imm, &[input]), [i64::from(input != 0)]);
+            assert_eq!(outputs(&cmp8, &[input]), [999 + (input - 8).signum() + 1]);
+        }
+    }
+
+    #[test]
+    fn test_day5_diagnostic() {
+        let prog = aoc_program!(5);
+        let outs = outputs(&prog.code, &[1]);
+        let (code, checks) = outs.split_last().unwrap();
+        assert!(checks.iter().all(|&c| c == 0), "failed checks {:?}", checks);
+        assert_ne!(*code, 0);
+        assert_eq!(outputs(&prog.code, &[5]).len(), 1);
+    }
+
+    #[test]
+    fn test_boost_self_test() {
+        let boost = boost();
+        // BOOST writes the opcodes it found broken before the keycode
+        assert_eq!(outputs(&boost.code, &[1]).len(), 1);
+        assert_eq!(outputs(&boost.code, &[2]), [51754]);
+    }
+}
@@ -0,0 +1,27 @@
+pub mod arcade;
+pub mod ascii;
+pub mod asm;
+pub mod block;
+pub mod budget;
+pub mod cfg;
+pub mod channel;
+pub mod compress;
+pub mod debugger;
+pub mod decompile;
+pub mod disasm;
+pub mod explore;
+pub mod int;
+pub mod mem;
+pub mod network;
+pub mod pipeline;
+pub mod program;
+pub mod replay;
+pub mod scaffold;
+pub mod scan;
+pub mod snapshot;
+pub mod trace;
+
+#[cfg(test)]
+mod fuzz;
+#[cfg(test)]
+mod test_util;
@@ -0,0 +1,358 @@
+use std::{
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::{Index, IndexMut},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+pub const PAGE_SIZE: usize = 1024;
+pub const DEFAULT_LIMIT: usize = 1 << 24;
+
+type Page = Arc<[i64; PAGE_SIZE]>;
+
+/// Paged Intcode memory. Pages are allocated on first write, every address
+/// that was never written reads as zero, writes at or above `limit` fail.
+///
+/// Clones share their pages until one side writes to them, so forking a
+/// machine costs a page table copy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "SparseMemory", try_from = "SparseMemory")]
+pub struct Memory {
+    pages: Vec<Option<Page>>,
+    len: usize,
+    limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitExceeded {
+    pub addr: usize,
+    pub limit: usize,
+}
+
+/// Page table `from_pages` refuses to build.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidPages {
+    LenOverLimit { len: usize, limit: usize },
+    PageOutOfRange { ix: usize, len: usize },
+    PageTooLong { ix: usize, words: usize },
+    DuplicatePage { ix: usize },
+}
+
+impl fmt::Display for InvalidPages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidPages::LenOverLimit { len, limit } => write!(f, "memory length {} over limit {}", len, limit),
+            InvalidPages::PageOutOfRange { ix, len } => write!(f, "page {} past memory length {}", ix, len),
+            InvalidPages::PageTooLong { ix, words } => write!(f, "page {} has {} words, over {}", ix, words, PAGE_SIZE),
+            InvalidPages::DuplicatePage { ix } => write!(f, "page {} given twice", ix),
+        }
+    }
+}
+
+impl std::error::Error for InvalidPages {}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory::with_limit(DEFAULT_LIMIT)
+    }
+
+    pub fn with_limit(limit: usize) -> Memory {
+        Memory {
+            pages: vec![],
+            len: 0,
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Never below `len`, what was written stays addressable.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(self.len);
+    }
+
+    /// One past the highest address ever written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn get(&self, addr: usize) -> i64 {
+        match self.pages.get(addr / PAGE_SIZE) {
+            Some(Some(page)) => page[addr % PAGE_SIZE],
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    pub fn set(&mut self, addr: usize, val: i64) -> Result<(), LimitExceeded> {
+        *self.cell_mut(addr)? = val;
+        Ok(())
+    }
+
+    #[inline]
+    fn cell_mut(&mut self, addr: usize) -> Result<&mut i64, LimitExceeded> {
+        if addr >= self.limit {
+            return Err(LimitExceeded { addr, limit: self.limit });
+        }
+        let page_ix = addr / PAGE_SIZE;
+        if page_ix >= self.pages.len() {
+            self.pages.resize_with(page_ix + 1, || None);
+        }
+        self.len = self.len.max(addr + 1);
+        let page = self.pages[page_ix].get_or_insert_with(|| Arc::new([0; PAGE_SIZE]));
+        Ok(&mut Arc::make_mut(page)[addr % PAGE_SIZE])
+    }
+
+    /// Number of allocated pages.
+    pub fn pages(&self) -> usize {
+        self.pages.iter().filter(|p| p.is_some()).count()
+    }
+
+    /// Number of allocated pages not shared with any clone.
+    pub fn unique_pages(&self) -> usize {
+        self.pages.iter().flatten().filter(|p| Arc::strong_count(p) == 1).count()
+    }
+
+    /// Allocated pages as `(page index, words)`.
+    pub fn page_iter(&self) -> impl Iterator<Item = (usize, &[i64; PAGE_SIZE])> + '_ {
+        self.pages.iter()
+            .enumerate()
+            .filter_map(|(ix, page)| page.as_deref().map(|p| (ix, p)))
+    }
+
+    /// Rebuilds memory from `page_iter` output. Fails on a `len` over
+    /// `limit`, a page past `len` or a page given twice before allocating
+    /// for it.
+    pub fn from_pages(
+        len: usize,
+        limit: usize,
+        pages: impl IntoIterator<Item = (usize, [i64; PAGE_SIZE])>,
+    ) -> Result<Memory, InvalidPages> {
+        if len > limit {
+            return Err(InvalidPages::LenOverLimit { len, limit });
+        }
+        let mut mem = Memory::with_limit(limit);
+        for (ix, page) in pages {
+            if ix.checked_mul(PAGE_SIZE).is_none_or(|start| start >= len) {
+                return Err(InvalidPages::PageOutOfRange { ix, len });
+            }
+            if ix >= mem.pages.len() {
+                mem.pages.resize_with(ix + 1, || None);
+            }
+            if mem.pages[ix].is_some() {
+                return Err(InvalidPages::DuplicatePage { ix });
+            }
+            mem.pages[ix] = Some(Arc::new(page));
+        }
+        mem.len = len;
+        Ok(mem)
+    }
+
+    /// Hash of the contents, equal for memories reading the same everywhere.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for (ix, page) in self.page_iter().filter(|(_, page)| page.iter().any(|&v| v != 0)) {
+            (ix, page).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    fn page(&self, ix: usize) -> Option<&Page> {
+        self.pages.get(ix).and_then(Option::as_ref)
+    }
+
+    /// True if both memories read the same everywhere, the exact check
+    /// behind a `content_hash` match. Shared pages are equal for free.
+    pub fn same_contents(&self, other: &Memory) -> bool {
+        (0..self.pages.len().max(other.pages.len())).all(|ix| match (self.page(ix), other.page(ix)) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b) || a == b,
+            (Some(p), None) | (None, Some(p)) => p.iter().all(|&v| v == 0),
+            (None, None) => true,
+        })
+    }
+
+    /// Page `ix` from its leading words, the rest zero.
+    pub(crate) fn page_from_words(ix: usize, words: &[i64]) -> Result<[i64; PAGE_SIZE], InvalidPages> {
+        if words.len() > PAGE_SIZE {
+            return Err(InvalidPages::PageTooLong { ix, words: words.len() });
+        }
+        let mut page = [0; PAGE_SIZE];
+        page[..words.len()].copy_from_slice(words);
+        Ok(page)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len).map(|addr| self.get(addr))
+    }
+
+    pub fn to_vec(&self) -> Vec<i64> {
+        self.iter().collect()
+    }
+}
+
+// serialized form, only allocated pages with their trailing zeros trimmed
+#[derive(Serialize, Deserialize)]
+struct SparseMemory {
+    len: usize,
+    limit: usize,
+    pages: Vec<(usize, Vec<i64>)>,
+}
+
+impl From<Memory> for SparseMemory {
+    fn from(mem: Memory) -> Self {
+        let pages = mem.page_iter()
+            .map(|(ix, page)| {
+                let used = page.iter().rposition(|&v| v != 0).map_or(0, |i| i + 1);
+                (ix, page[..used].to_vec())
+            })
+            .collect();
+        SparseMemory { len: mem.len, limit: mem.limit, pages }
+    }
+}
+
+impl TryFrom<SparseMemory> for Memory {
+    type Error = InvalidPages;
+
+    fn try_from(sparse: SparseMemory) -> Result<Self, InvalidPages> {
+        let pages = sparse.pages.iter()
+            .map(|(ix, words)| Ok((*ix, Memory::page_from_words(*ix, words)?)))
+            .collect::<Result<Vec<_>, InvalidPages>>()?;
+        Memory::from_pages(sparse.len, sparse.limit, pages)
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
+}
+
+impl From<&[i64]> for Memory {
+    fn from(prog: &[i64]) -> Self {
+        let mut mem = Memory::with_limit(DEFAULT_LIMIT.max(prog.len()));
+        for (addr, &val) in prog.iter().enumerate() {
+            mem.set(addr, val).unwrap();
+        }
+        mem
+    }
+}
+
+impl From<Vec<i64>> for Memory {
+    fn from(prog: Vec<i64>) -> Self {
+        Memory::from(prog.as_slice())
+    }
+}
+
+impl Index<usize> for Memory {
+    type Output = i64;
+
+    fn index(&self, addr: usize) -> &i64 {
+        match self.pages.get(addr / PAGE_SIZE) {
+            Some(Some(page)) => &page[addr % PAGE_SIZE],
+            _ => &0,
+        }
+    }
+}
+
+impl IndexMut<usize> for Memory {
+    fn index_mut(&mut self, addr: usize) -> &mut i64 {
+        match self.cell_mut(addr) {
+            Ok(cell) => cell,
+            Err(e) => panic!("Address {} over memory limit {}", e.addr, e.limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_zero_beyond_end() {
+        let mem = Memory::from(vec![1, 2, 3]);
+        assert_eq!(mem.len(), 3);
+        assert_eq!(mem.get(2), 3);
+        assert_eq!(mem.get(3), 0);
+        assert_eq!(mem[1_000_000], 0);
+        assert_eq!(mem.pages(), 1);
+    }
+
+    #[test]
+    fn test_grows_on_write() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+        mem.set(50_000, 7).unwrap();
+        mem[10] = 5;
+        assert_eq!(mem.len(), 50_001);
+        assert_eq!(mem.get(50_000), 7);
+        assert_eq!(mem.get(10), 5);
+        assert_eq!(mem.get(49_999), 0);
+        assert_eq!(mem.pages(), 2);
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+        mem.set(5000, 4).unwrap();
+        let mut fork = mem.clone();
+        assert_eq!(fork.unique_pages(), 0);
+        fork[1] = 20;
+        assert_eq!(fork.unique_pages(), 1);
+        assert_eq!(mem.unique_pages(), 1);
+        assert_eq!((mem.get(1), fork.get(1)), (2, 20));
+        assert_eq!(fork.get(5000), 4);
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+        let fork = mem.clone();
+        mem.set(5000, 0).unwrap();
+        assert_eq!(mem.content_hash(), fork.content_hash());
+        assert!(mem.same_contents(&fork) && fork.same_contents(&mem));
+        mem[2] = 4;
+        assert_ne!(mem.content_hash(), fork.content_hash());
+        assert!(!mem.same_contents(&fork));
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut mem = Memory::with_limit(100);
+        assert_eq!(mem.set(99, 1), Ok(()));
+        assert_eq!(mem.set(100, 1), Err(LimitExceeded { addr: 100, limit: 100 }));
+        assert_eq!(mem.to_vec().len(), 100);
+        mem.set_limit(10);
+        assert_eq!(mem.limit(), 100);
+    }
+
+    #[test]
+    fn test_from_pages() {
+        let page = |ix: usize| (ix, [ix as i64; PAGE_SIZE]);
+        let mem = Memory::from_pages(PAGE_SIZE + 1, 5000, [page(1)]).unwrap();
+        assert_eq!((mem.get(0), mem.get(PAGE_SIZE), mem.len()), (0, 1, PAGE_SIZE + 1));
+        assert_eq!(Memory::from_pages(101, 100, []).unwrap_err(), InvalidPages::LenOverLimit { len: 101, limit: 100 });
+        assert_eq!(Memory::from_pages(PAGE_SIZE, 5000, [page(1)]).unwrap_err(), InvalidPages::PageOutOfRange { ix: 1, len: PAGE_SIZE });
+        let err = Memory::from_pages(usize::MAX, usize::MAX, [page(usize::MAX / 2)]).unwrap_err();
+        assert_eq!(err, InvalidPages::PageOutOfRange { ix: usize::MAX / 2, len: usize::MAX });
+        assert_eq!(Memory::from_pages(3 * PAGE_SIZE, 5000, [page(1), page(2), page(1)]).unwrap_err(), InvalidPages::DuplicatePage { ix: 1 });
+    }
+
+    #[test]
+    fn test_sparse_pages_checked() {
+        let sparse = |pages| SparseMemory { len: 2 * PAGE_SIZE, limit: 5000, pages };
+        let mem = Memory::try_from(sparse(vec![(1, vec![7, 8])])).unwrap();
+        assert_eq!((mem.get(PAGE_SIZE + 1), mem.get(PAGE_SIZE + 2)), (8, 0));
+        let err = Memory::try_from(sparse(vec![(0, vec![1; PAGE_SIZE + 1])])).unwrap_err();
+        assert_eq!(err, InvalidPages::PageTooLong { ix: 0, words: PAGE_SIZE + 1 });
+        let err = Memory::try_from(sparse(vec![(0, vec![1]), (0, vec![2])])).unwrap_err();
+        assert_eq!(err, InvalidPages::DuplicatePage { ix: 0 });
+    }
+}
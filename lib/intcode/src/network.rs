@@ -0,0 +1,358 @@
+use std::fmt;
+
+use crate::{int::{Computer, ComputerState, VmError}, program::Program};
+
+/// Words sent by a machine as one unit: destination address, then data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub src: i64,
+    pub dst: i64,
+    pub data: Vec<i64>,
+}
+
+/// What the network does after handing a packet or an idle network to the
+/// router.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    /// Delivers a packet to a machine. On idle this wakes the network.
+    Inject(Packet),
+    Stop,
+}
+
+/// Hook for everything that isn't a machine in the network, like the NAT.
+pub trait Router {
+    /// Packet addressed outside the network.
+    fn route(&mut self, _packet: &Packet) -> Control {
+        Control::Continue
+    }
+
+    /// Every machine waits for input and nothing is in flight. Not
+    /// injecting a packet here leaves the network deadlocked.
+    fn idle(&mut self, _frame: u64) -> Control {
+        Control::Continue
+    }
+}
+
+/// No router, packets leaving the network are dropped.
+impl Router for () {}
+
+/// The NAT at address 255: keeps the last packet sent to it and sends it
+/// to machine 0 when the network is idle. Stops instead of sending the same
+/// data twice in a row, the repeated packet still ends up in `sent`.
+#[derive(Debug, Clone, Default)]
+pub struct Nat {
+    pub first: Option<Packet>,
+    pub last: Option<Packet>,
+    pub sent: Vec<Packet>,
+    /// Data sent to machine 0 when the network idles before any packet
+    /// reached the NAT. Without it that idle network is a deadlock.
+    pub wake: Option<Vec<i64>>,
+}
+
+pub const NAT_ADDR: i64 = 255;
+
+impl Router for Nat {
+    fn route(&mut self, packet: &Packet) -> Control {
+        if packet.dst == NAT_ADDR {
+            self.first.get_or_insert_with(|| packet.clone());
+            self.last = Some(packet.clone());
+        }
+        Control::Continue
+    }
+
+    fn idle(&mut self, _frame: u64) -> Control {
+        let Some(data) = self.last.as_ref().map(|p| &p.data).or(self.wake.as_ref()) else {
+            return Control::Continue;
+        };
+        let packet = Packet { src: NAT_ADDR, dst: 0, data: data.clone() };
+        let repeated = self.sent.last() == Some(&packet);
+        self.sent.push(packet.clone());
+        if repeated {
+            Control::Stop
+        } else {
+            Control::Inject(packet)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The router asked to stop.
+    Stopped { frame: u64 },
+    /// Idle and the router didn't wake anyone up.
+    Deadlock { frame: u64 },
+    Halted { frame: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetError {
+    pub machine: usize,
+    pub error: VmError,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "machine {}: {}", self.machine, self.error)
+    }
+}
+
+impl std::error::Error for NetError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameStats {
+    pub frame: u64,
+    /// Packets sent by machines.
+    pub sent: usize,
+    /// Packets handed to the router.
+    pub external: usize,
+    /// Packets delivered to machines, injected ones included.
+    pub delivered: usize,
+    /// Machines that ended the frame waiting for input.
+    pub waiting: usize,
+    pub idle: bool,
+}
+
+/// Machines exchanging packets, scheduled round robin. Every frame each
+/// machine runs until its next input or output, then the packets sent
+/// during the frame are delivered in order and machines with nothing
+/// queued get a `-1`. The same machines always give the same packet log.
+pub struct Network {
+    pub machines: Vec<Computer>,
+    states: Vec<ComputerState>,
+    packet_size: usize,
+    pub frame: u64,
+    /// Every packet with the frame it was sent in.
+    pub log: Vec<(u64, Packet)>,
+    pub stats: Vec<FrameStats>,
+}
+
+impl Network {
+    /// `n` copies of `prog`, each booted with its address as first input.
+    pub fn new(prog: &Program, n: usize) -> Network {
+        Network::from_machines((0..n).map(|i| Computer::new(prog).with_input([i as i64])).collect())
+    }
+
+    pub fn from_machines(machines: Vec<Computer>) -> Network {
+        Network {
+            states: vec![ComputerState::ReadInt; machines.len()],
+            machines,
+            packet_size: 3,
+            frame: 0,
+            log: vec![],
+            stats: vec![],
+        }
+    }
+
+    /// Words per packet including the address, 3 by default.
+    pub fn with_packet_size(mut self, size: usize) -> Network {
+        assert!(size > 0, "Packets need at least an address");
+        self.packet_size = size;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    fn machine(&self, addr: i64) -> Option<usize> {
+        usize::try_from(addr).ok().filter(|&i| i < self.machines.len())
+    }
+
+    // injected packets for addresses outside the network are dropped
+    fn deliver(&mut self, packet: Packet, stats: &mut FrameStats) {
+        if let Some(i) = self.machine(packet.dst) {
+            self.machines[i].ins.extend(packet.data.iter());
+            stats.delivered += 1;
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.machines.iter()
+            .zip(self.states.iter())
+            .all(|(c, &state)| state == ComputerState::Halted
+                || (c.ins.is_empty() && c.outs.is_empty() && state != ComputerState::WriteInt))
+    }
+
+    pub fn step_frame(&mut self, router: &mut impl Router) -> Result<Option<Outcome>, NetError> {
+        self.frame += 1;
+        let frame = self.frame;
+        let mut stats = FrameStats { frame, ..FrameStats::default() };
+        let mut stop = false;
+        let mut queued = vec![];
+        for i in 0..self.machines.len() {
+            if self.states[i] == ComputerState::Halted {
+                continue;
+            }
+            let comp = &mut self.machines[i];
+            self.states[i] = comp.run().map_err(|error| NetError { machine: i, error })?;
+            if comp.outs.len() < self.packet_size {
+                continue;
+            }
+            let mut words = comp.outs.drain(..self.packet_size);
+            let dst = words.next().unwrap();
+            let packet = Packet { src: i as i64, dst, data: words.collect() };
+            self.log.push((frame, packet.clone()));
+            stats.sent += 1;
+            if self.machine(dst).is_some() {
+                queued.push(packet);
+                continue;
+            }
+            stats.external += 1;
+            match router.route(&packet) {
+                Control::Continue => {}
+                Control::Inject(reply) => {
+                    self.log.push((frame, reply.clone()));
+                    queued.push(reply);
+                }
+                Control::Stop => stop = true,
+            }
+        }
+        for packet in queued {
+            self.deliver(packet, &mut stats);
+        }
+
+        let mut outcome = None;
+        if self.states.iter().all(|&s| s == ComputerState::Halted) {
+            outcome = Some(Outcome::Halted { frame });
+        } else if stop {
+            outcome = Some(Outcome::Stopped { frame });
+        } else if self.is_idle() {
+            stats.idle = true;
+            match router.idle(frame) {
+                Control::Continue => outcome = Some(Outcome::Deadlock { frame }),
+                Control::Inject(packet) => {
+                    self.log.push((frame, packet.clone()));
+                    self.deliver(packet, &mut stats);
+                }
+                Control::Stop => outcome = Some(Outcome::Stopped { frame }),
+            }
+        }
+
+        for (comp, &state) in self.machines.iter_mut().zip(self.states.iter()) {
+            if state != ComputerState::Halted && comp.ins.is_empty() {
+                comp.ins.push_back(-1);
+            }
+        }
+        stats.waiting = self.states.iter().filter(|&&s| s == ComputerState::ReadInt).count();
+        self.stats.push(stats);
+        Ok(outcome)
+    }
+
+    pub fn run(&mut self, router: &mut impl Router) -> Result<Outcome, NetError> {
+        loop {
+            if let Some(outcome) = self.step_frame(router)? {
+                return Ok(outcome);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::assemble;
+
+    // machine 0 sends (7, 8) to machine 1, every machine forwards what it
+    // receives to the next one, the last one to the NAT
+    const RING: &str = "
+            in [addr]
+            jt [addr], #recv
+            out #1
+            out #7
+            out #8
+        recv: in [x]
+            eq [x], #-1, [tmp]
+            jt [tmp], #recv
+            in [y]
+            add [addr], #1, [dst]
+            eq [dst], #3, [tmp]
+            jf [tmp], #send
+            add #255, #0, [dst]
+        send: out [dst]
+            out [x]
+            out [y]
+            jf #0, #recv
+        addr: .data 0
+        x: .data 0
+        y: .data 0
+        dst: .data 0
+        tmp: .data 0
+    ";
+
+    fn ring() -> Network {
+        Network::new(&Program::from(assemble(RING).unwrap().code), 3)
+    }
+
+    fn packet(src: i64, dst: i64, data: &[i64]) -> Packet {
+        Packet { src, dst, data: data.to_vec() }
+    }
+
+    #[test]
+    fn test_nat() {
+        let mut net = ring();
+        let mut nat = Nat::default();
+        assert_eq!(net.run(&mut nat), Ok(Outcome::Stopped { frame: 20 }));
+        assert_eq!(nat.first, Some(packet(2, 255, &[7, 8])));
+        assert_eq!(nat.sent, [packet(255, 0, &[7, 8]), packet(255, 0, &[7, 8])]);
+        let log: Vec<(u64, Packet)> = net.log.iter().take(5).cloned().collect();
+        assert_eq!(log, [
+            (3, packet(0, 1, &[7, 8])),
+            (6, packet(1, 2, &[7, 8])),
+            (9, packet(2, 255, &[7, 8])),
+            (10, packet(255, 0, &[7, 8])),
+            (13, packet(0, 1, &[7, 8])),
+        ]);
+        assert_eq!(net.stats.iter().filter(|s| s.idle).count(), 2);
+    }
+
+    #[test]
+    fn test_nat_wake() {
+        // every machine waits for a packet and sends it on to the NAT
+        let prog = Program::from(assemble("
+                in [addr]
+            recv: in [x]
+                eq [x], #-1, [tmp]
+                jt [tmp], #recv
+                in [y]
+                out #255
+                out [x]
+                out [y]
+                jf #0, #recv
+            addr: .data 0
+            x: .data 0
+            y: .data 0
+            tmp: .data 0
+        ").unwrap().code);
+        assert_eq!(Network::new(&prog, 2).run(&mut Nat::default()), Ok(Outcome::Deadlock { frame: 1 }));
+        let mut nat = Nat { wake: Some(vec![7, 8]), ..Nat::default() };
+        assert!(matches!(Network::new(&prog, 2).run(&mut nat), Ok(Outcome::Stopped { .. })));
+        assert_eq!(nat.first, Some(packet(0, 255, &[7, 8])));
+        assert_eq!(nat.sent, [packet(255, 0, &[7, 8]), packet(255, 0, &[7, 8])]);
+    }
+
+    #[test]
+    fn test_deadlock_and_stats() {
+        let mut net = ring();
+        assert_eq!(net.run(&mut ()), Ok(Outcome::Deadlock { frame: 10 }));
+        assert_eq!(net.log.len(), 3);
+        assert_eq!(net.stats[2], FrameStats { frame: 3, sent: 1, external: 0, delivered: 1, waiting: 2, idle: false });
+        assert_eq!(net.stats[8], FrameStats { frame: 9, sent: 1, external: 1, delivered: 0, waiting: 2, idle: false });
+        assert!(net.stats[9].idle);
+    }
+
+    #[test]
+    fn test_halt_and_errors() {
+        let prog = Program::from(assemble("in [9]\nout #5\nout #1\nout #2\nhlt").unwrap().code);
+        let mut net = Network::new(&prog, 2);
+        assert_eq!(net.run(&mut ()), Ok(Outcome::Halted { frame: 4 }));
+        assert_eq!(net.log.len(), 2);
+
+        let bad = Network::from_machines(vec![Computer::new(&Program::from(vec![42]))]).run(&mut ());
+        assert_eq!(bad, Err(NetError { machine: 0, error: VmError::UnknownOpCode { eip: 0, code: 42 } }));
+    }
+}
@@ -0,0 +1,197 @@
+use std::{fmt, thread};
+
+use crate::{int::{Computer, ComputerState, VmError}, program::Program};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    /// Outputs of the last machine leave the pipeline.
+    Line,
+    /// Outputs of the last machine also feed the first one.
+    Ring,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PipelineError {
+    Vm { machine: usize, error: VmError },
+    /// Machines wait for input nobody is going to send.
+    Deadlock,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Vm { machine, error } => write!(f, "machine {}: {}", machine, error),
+            PipelineError::Deadlock => write!(f, "pipeline deadlocked"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Machines chained output to input, like the aoc7 amplifiers.
+#[derive(Clone)]
+pub struct Pipeline {
+    pub machines: Vec<Computer>,
+    pub topology: Topology,
+    /// Everything the last machine wrote.
+    pub outputs: Vec<i64>,
+}
+
+impl Pipeline {
+    pub fn from_machines(machines: Vec<Computer>, topology: Topology) -> Pipeline {
+        Pipeline { machines, topology, outputs: vec![] }
+    }
+
+    /// One copy of `prog` per phase setting, each getting its phase as first input.
+    pub fn with_phases(prog: &Program, phases: &[i64], topology: Topology) -> Pipeline {
+        let machines = phases.iter().map(|&phase| Computer::new(prog).with_input([phase])).collect();
+        Pipeline::from_machines(machines, topology)
+    }
+
+    /// Feeds `input` to the first machine and runs until every machine
+    /// halts. Returns the last value the last machine wrote.
+    pub fn run(&mut self, input: impl IntoIterator<Item = i64>) -> Result<Option<i64>, PipelineError> {
+        let n = self.machines.len();
+        if let Some(first) = self.machines.first_mut() {
+            first.ins.extend(input);
+        }
+        let mut halted = vec![false; n];
+        while !halted.iter().all(|&h| h) {
+            let mut progress = false;
+            for (i, done) in halted.iter_mut().enumerate() {
+                if *done {
+                    continue;
+                }
+                let comp = &mut self.machines[i];
+                let waiting = comp.ins.is_empty();
+                let state = loop {
+                    match comp.run() {
+                        Ok(ComputerState::WriteInt) => continue,
+                        Ok(state) => break state,
+                        Err(error) => return Err(PipelineError::Vm { machine: i, error }),
+                    }
+                };
+                *done = state == ComputerState::Halted;
+                let outs: Vec<i64> = comp.outs.drain(..).collect();
+                progress |= *done || !waiting || !outs.is_empty();
+                if i + 1 < n {
+                    self.machines[i + 1].ins.extend(outs);
+                } else {
+                    if self.topology == Topology::Ring {
+                        self.machines[0].ins.extend(outs.iter());
+                    }
+                    self.outputs.extend(outs);
+                }
+            }
+            if !progress {
+                return Err(PipelineError::Deadlock);
+            }
+        }
+        Ok(self.outputs.last().copied())
+    }
+}
+
+// rearranges into the next lexicographic permutation, false after the last one
+fn next_permutation(v: &mut [i64]) -> bool {
+    let Some(i) = v.windows(2).rposition(|w| w[0] < w[1]) else {
+        return false;
+    };
+    let j = v.iter().rposition(|&x| x > v[i]).unwrap();
+    v.swap(i, j);
+    v[i + 1..].reverse();
+    true
+}
+
+pub fn permutations(items: &[i64]) -> Vec<Vec<i64>> {
+    let mut perm = items.to_vec();
+    perm.sort_unstable();
+    let mut perms = vec![perm.clone()];
+    while next_permutation(&mut perm) {
+        perms.push(perm.clone());
+    }
+    perms
+}
+
+/// Runs the pipeline with input 0 for every ordering of `phases`, spread
+/// over all cores. Returns the phases giving the highest output, the
+/// lexicographically first ones on ties.
+pub fn best_phases(prog: &Program, phases: &[i64], topology: Topology) -> Result<Option<(Vec<i64>, i64)>, PipelineError> {
+    let perms = permutations(phases);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = perms.len().div_ceil(threads).max(1);
+    let results: Vec<Result<Option<(usize, i64)>, PipelineError>> = thread::scope(|s| {
+        let handles: Vec<_> = perms.chunks(chunk).enumerate().map(|(c, chunk_perms)| {
+            s.spawn(move || {
+                let mut best: Option<(usize, i64)> = None;
+                for (k, perm) in chunk_perms.iter().enumerate() {
+                    let Some(out) = Pipeline::with_phases(prog, perm, topology).run([0])? else {
+                        continue;
+                    };
+                    if best.is_none_or(|(_, b)| out > b) {
+                        best = Some((c * chunk + k, out));
+                    }
+                }
+                Ok(best)
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut best: Option<(usize, i64)> = None;
+    for res in results {
+        if let Some((ix, out)) = res? {
+            if best.is_none_or(|(_, b)| out > b) {
+                best = Some((ix, out));
+            }
+        }
+    }
+    Ok(best.map(|(ix, out)| (perms[ix].clone(), out)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prog(s: &str) -> Program {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_line() {
+        let p = prog("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
+        let mut pipeline = Pipeline::with_phases(&p, &[4, 3, 2, 1, 0], Topology::Line);
+        assert_eq!(pipeline.run([0]), Ok(Some(43210)));
+        assert_eq!(best_phases(&p, &[0, 1, 2, 3, 4], Topology::Line), Ok(Some((vec![4, 3, 2, 1, 0], 43210))));
+    }
+
+    #[test]
+    fn test_ring() {
+        let p = prog("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5");
+        let mut pipeline = Pipeline::with_phases(&p, &[9, 8, 7, 6, 5], Topology::Ring);
+        assert_eq!(pipeline.run([0]), Ok(Some(139629729)));
+        assert_eq!(pipeline.outputs.len(), 5);
+        assert_eq!(best_phases(&p, &[5, 6, 7, 8, 9], Topology::Ring), Ok(Some((vec![9, 8, 7, 6, 5], 139629729))));
+    }
+
+    #[test]
+    fn test_deadlock_and_errors() {
+        // second machine wants two inputs but only gets one
+        let mut pipeline = Pipeline::from_machines(vec![
+            Computer::new(&prog("3,0,4,0,99")),
+            Computer::new(&prog("3,0,3,0,99")),
+        ], Topology::Line);
+        assert_eq!(pipeline.run([1]), Err(PipelineError::Deadlock));
+
+        let mut pipeline = Pipeline::with_phases(&prog("3,0,42"), &[1, 2], Topology::Ring);
+        assert_eq!(pipeline.run([]), Err(PipelineError::Vm {
+            machine: 0,
+            error: VmError::UnknownOpCode { eip: 2, code: 42 },
+        }));
+    }
+
+    #[test]
+    fn test_permutations() {
+        assert_eq!(permutations(&[3, 1, 2]), [[1, 2, 3], [1, 3, 2], [2, 1, 3], [2, 3, 1], [3, 1, 2], [3, 2, 1]]);
+        assert_eq!(permutations(&[5, 6, 7, 8, 9]).len(), 120);
+    }
+}
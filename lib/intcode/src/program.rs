@@ -0,0 +1,143 @@
+use std::{fmt, fs, io, path::Path, str::FromStr};
+
+/// Intcode program as comma separated integers, the `prog.txt` format.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Program {
+    pub code: Vec<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: bad token \"{}\"", self.line, self.column, self.token)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<ParseError> for LoadError {
+    fn from(e: ParseError) -> Self {
+        LoadError::Parse(e)
+    }
+}
+
+impl Program {
+    pub fn load(path: impl AsRef<Path>) -> Result<Program, LoadError> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.code.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty()
+    }
+}
+
+impl FromStr for Program {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split(',').collect();
+        let mut code = Vec::with_capacity(tokens.len());
+        let mut offset = 0;
+        for (i, token) in tokens.iter().enumerate() {
+            let start = offset + token.len() - token.trim_start().len();
+            offset += token.len() + 1;
+            let trimmed = token.trim();
+            // tolerate empty input and a trailing comma
+            if trimmed.is_empty() && i == tokens.len() - 1 {
+                continue;
+            }
+            match trimmed.parse::<i64>() {
+                Ok(val) => code.push(val),
+                Err(_) => {
+                    let (line, column) = text_position(s, start);
+                    return Err(ParseError { line, column, token: trimmed.to_owned() });
+                }
+            }
+        }
+        Ok(Program { code })
+    }
+}
+
+// 1-based line and column of a byte offset
+fn text_position(s: &str, offset: usize) -> (usize, usize) {
+    let before = &s[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+impl From<Vec<i64>> for Program {
+    fn from(code: Vec<i64>) -> Self {
+        Program { code }
+    }
+}
+
+impl From<&[i64]> for Program {
+    fn from(code: &[i64]) -> Self {
+        Program { code: code.to_vec() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("1,0,0,3,99".parse(), Ok(Program::from(vec![1, 0, 0, 3, 99])));
+        assert_eq!("104,-7, 99 \n\n".parse(), Ok(Program::from(vec![104, -7, 99])));
+        assert_eq!("1,\n2,\n3,\n".parse(), Ok(Program::from(vec![1, 2, 3])));
+        assert_eq!("".parse(), Ok(Program::default()));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert_eq!("1,2,x3,4".parse::<Program>(), Err(ParseError {
+            line: 1,
+            column: 5,
+            token: "x3".to_owned(),
+        }));
+        assert_eq!("1,2,\n 3,,4".parse::<Program>(), Err(ParseError {
+            line: 2,
+            column: 4,
+            token: "".to_owned(),
+        }));
+        assert_eq!("1 2,3".parse::<Program>(), Err(ParseError {
+            line: 1,
+            column: 1,
+            token: "1 2".to_owned(),
+        }));
+    }
+}
@@ -0,0 +1,331 @@
+use std::{fmt, fs, io, path::Path, str::FromStr};
+
+use crate::{
+    int::{Computer, ComputerState, OpCode, VmError},
+    program::Program,
+    trace::{TraceEvent, Tracer},
+};
+
+const HEADER: &str = "intcode-session";
+
+/// I/O of a session. `step` counts the instructions executed before the
+/// one reading, writing or halting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Input { step: u64, val: i64 },
+    Output { step: u64, val: i64 },
+    Halt { step: u64 },
+}
+
+impl Event {
+    pub fn step(&self) -> u64 {
+        match *self {
+            Event::Input { step, .. } | Event::Output { step, .. } | Event::Halt { step } => step,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Input { step, val } => write!(f, "in {} {}", step, val),
+            Event::Output { step, val } => write!(f, "out {} {}", step, val),
+            Event::Halt { step } => write!(f, "hlt {}", step),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let num = |i: usize| words[i].parse().map_err(|_| ());
+        match words[..] {
+            ["in", _, _] => Ok(Event::Input { step: num(1)?, val: words[2].parse().map_err(|_| ())? }),
+            ["out", _, _] => Ok(Event::Output { step: num(1)?, val: words[2].parse().map_err(|_| ())? }),
+            ["hlt", _] => Ok(Event::Halt { step: num(1)? }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// FNV-1a over the program words, identifies the program a session belongs to.
+pub fn program_hash(code: &[i64]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in code.iter().flat_map(|w| w.to_le_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Recorded run of a program. Saved as text, a header line with the
+/// program hash followed by one event per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub program: u64,
+    pub events: Vec<Event>,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    /// Line number of a malformed line, 1-based.
+    Parse(usize),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(e) => write!(f, "{}", e),
+            SessionError::Parse(line) => write!(f, "bad session line {}", line),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(e: io::Error) -> Self {
+        SessionError::Io(e)
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {:016x}", HEADER, self.program)?;
+        for event in self.events.iter() {
+            writeln!(f, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Session {
+    type Err = SessionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines();
+        let program = lines.next()
+            .and_then(|header| header.strip_prefix(HEADER))
+            .and_then(|hash| u64::from_str_radix(hash.trim(), 16).ok())
+            .ok_or(SessionError::Parse(1))?;
+        let events = lines.enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| line.parse().map_err(|_| SessionError::Parse(i + 2)))
+            .collect::<Result<_, _>>()?;
+        Ok(Session { program, events })
+    }
+}
+
+/// What the replayed machine did where a recorded event was expected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Observed {
+    InputRequest { step: u64 },
+    Output { step: u64, val: i64 },
+    Halt { step: u64 },
+    Error { step: u64, error: VmError },
+    /// Passed the expected step without any I/O.
+    Running { step: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayError {
+    WrongProgram { expected: u64, found: u64 },
+    /// First event the replay didn't reproduce, `index` into `events`.
+    Diverged { index: usize, expected: Event, found: Observed },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::WrongProgram { expected, found } => {
+                write!(f, "session is for program {:016x}, not {:016x}", expected, found)
+            }
+            ReplayError::Diverged { index, expected, found } => {
+                write!(f, "event {}: expected \"{}\", found {:?}", index, expected, found)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Session {
+    pub fn new(prog: &Program) -> Session {
+        Session { program: program_hash(&prog.code), events: vec![] }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Session, SessionError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn inputs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.events.iter().filter_map(|e| match *e {
+            Event::Input { val, .. } => Some(val),
+            _ => None,
+        })
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = i64> + '_ {
+        self.events.iter().filter_map(|e| match *e {
+            Event::Output { val, .. } => Some(val),
+            _ => None,
+        })
+    }
+
+    /// Runs `prog` from the start feeding the recorded inputs when it asks
+    /// for them, and checks every event happens at the recorded step.
+    /// Stops after the last event.
+    pub fn replay(&self, prog: &Program) -> Result<(), ReplayError> {
+        let found = program_hash(&prog.code);
+        if found != self.program {
+            return Err(ReplayError::WrongProgram { expected: self.program, found });
+        }
+        let mut comp = Computer::new(prog);
+        let mut step = 0;
+        for (index, &expected) in self.events.iter().enumerate() {
+            let found = loop {
+                if step > expected.step() {
+                    break Observed::Running { step };
+                }
+                match comp.step() {
+                    Ok(None | Some(ComputerState::BudgetExhausted)) => step += 1,
+                    Ok(Some(ComputerState::ReadInt)) => break Observed::InputRequest { step },
+                    Ok(Some(ComputerState::WriteInt)) => {
+                        break Observed::Output { step, val: comp.outs.pop_back().unwrap() }
+                    }
+                    Ok(Some(ComputerState::Halted)) => break Observed::Halt { step },
+                    Err(error) => break Observed::Error { step, error },
+                }
+            };
+            match (expected, found) {
+                (Event::Input { step: s, val }, Observed::InputRequest { step: o }) if s == o => {
+                    comp.ins.push_back(val);
+                }
+                (Event::Output { step: s, val: v }, Observed::Output { step: o, val: w }) if s == o && v == w => {
+                    step += 1;
+                }
+                (Event::Halt { step: s }, Observed::Halt { step: o }) if s == o => {}
+                _ => return Err(ReplayError::Diverged { index, expected, found }),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Records a session while the machine runs with `run_traced`. Attach it
+/// before the first instruction so steps line up on replay.
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    pub session: Session,
+    steps: u64,
+}
+
+impl Recorder {
+    pub fn new(prog: &Program) -> Recorder {
+        Recorder { session: Session::new(prog), steps: 0 }
+    }
+}
+
+impl Tracer for Recorder {
+    fn trace(&mut self, event: &TraceEvent) {
+        let step = self.steps;
+        let recorded = match event.op.code {
+            OpCode::Read => event.write.map(|(_, val)| Event::Input { step, val }),
+            OpCode::Write => event.output.map(|val| Event::Output { step, val }),
+            // a halted machine halts again every time it runs
+            OpCode::Halt if matches!(self.session.events.last(), Some(Event::Halt { .. })) => return,
+            OpCode::Halt => Some(Event::Halt { step }),
+            _ => None,
+        };
+        self.session.events.extend(recorded);
+        self.steps += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm::assemble, test_util::boost};
+
+    // sums pairs of inputs until it reads a 0
+    const ADDER: &str = "
+        loop: in [a]
+            jf [a], #end
+            in [b]
+            add [a], [b], [a]
+            out [a]
+            jf #0, #loop
+        end: hlt
+        a: .data 0
+        b: .data 0
+    ";
+
+    fn record(prog: &Program, inputs: &[i64]) -> Session {
+        let mut comp = Computer::new(prog);
+        let mut recorder = Recorder::new(prog);
+        // feed inputs one at a time like an interactive driver would
+        for &val in inputs {
+            while comp.run_traced(&mut recorder) == Ok(ComputerState::WriteInt) {}
+            comp.ins.push_back(val);
+        }
+        while comp.run_traced(&mut recorder) == Ok(ComputerState::WriteInt) {}
+        comp.run_traced(&mut recorder).unwrap();
+        recorder.session
+    }
+
+    #[test]
+    fn test_record() {
+        let prog = Program::from(assemble(ADDER).unwrap().code);
+        let session = record(&prog, &[2, 3, 0]);
+        assert_eq!(session.events, [
+            Event::Input { step: 0, val: 2 },
+            Event::Input { step: 2, val: 3 },
+            Event::Output { step: 4, val: 5 },
+            Event::Input { step: 6, val: 0 },
+            Event::Halt { step: 8 },
+        ]);
+        assert_eq!(session.outputs().collect::<Vec<_>>(), [5]);
+        assert_eq!(session.to_string().parse::<Session>().unwrap(), session);
+        assert_eq!(session.replay(&prog), Ok(()));
+    }
+
+    #[test]
+    fn test_divergence() {
+        let prog = Program::from(assemble(ADDER).unwrap().code);
+        let mut session = record(&prog, &[2, 3, 0]);
+        session.events[2] = Event::Output { step: 4, val: 6 };
+        assert_eq!(session.replay(&prog), Err(ReplayError::Diverged {
+            index: 2,
+            expected: Event::Output { step: 4, val: 6 },
+            found: Observed::Output { step: 4, val: 5 },
+        }));
+
+        session.events[2] = Event::Output { step: 3, val: 5 };
+        assert_eq!(session.replay(&prog), Err(ReplayError::Diverged {
+            index: 2,
+            expected: Event::Output { step: 3, val: 5 },
+            found: Observed::Running { step: 4 },
+        }));
+
+        let mut patched = prog.clone();
+        patched.code[8] = 2;
+        assert!(matches!(session.replay(&patched), Err(ReplayError::WrongProgram { .. })));
+        assert!(matches!("intcode-session 00\nin 1".parse::<Session>(), Err(SessionError::Parse(2))));
+    }
+
+    #[test]
+    fn test_boost_session() {
+        let boost = boost();
+        let session = record(&boost, &[1]);
+        assert_eq!(session.inputs().collect::<Vec<_>>(), [1]);
+        assert_eq!(session.outputs().count(), 1);
+        assert_eq!(session.replay(&boost), Ok(()));
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::HashSet, fmt};
+
+use crate::{
+    ascii::AsciiComputer,
+    explore::{Dir, Pos},
+    int::VmError,
+};
+
+/// Movement rule for the vacuum robot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Left,
+    Right,
+    Move(usize),
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Left => write!(f, "L"),
+            Command::Right => write!(f, "R"),
+            Command::Move(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// Scaffolding seen by the cameras: `#` is scaffold, `^v<>` the robot
+/// standing on it facing that way, `X` the robot tumbling through space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scaffold {
+    pub width: usize,
+    pub height: usize,
+    tiles: Vec<bool>,
+    pub robot: Option<(Pos, Dir)>,
+}
+
+impl Scaffold {
+    pub fn parse(view: &str) -> Scaffold {
+        let lines: Vec<&str> = view.lines().filter(|l| !l.is_empty()).collect();
+        let width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+        let mut tiles = vec![false; width * lines.len()];
+        let mut robot = None;
+        for (y, line) in lines.iter().enumerate() {
+            for (x, ch) in line.bytes().enumerate() {
+                let dir = match ch {
+                    b'^' => Some(Dir::North),
+                    b'v' => Some(Dir::South),
+                    b'<' => Some(Dir::West),
+                    b'>' => Some(Dir::East),
+                    _ => None,
+                };
+                if let Some(dir) = dir {
+                    robot = Some(((x as i64, y as i64), dir));
+                }
+                tiles[y * width + x] = ch == b'#' || dir.is_some();
+            }
+        }
+        Scaffold { width, height: lines.len(), tiles, robot }
+    }
+
+    /// Reads the camera view up to the empty line ending it.
+    pub fn read(ascii: &mut AsciiComputer) -> Result<Scaffold, VmError> {
+        let mut view = String::new();
+        while let Some(line) = ascii.read_line()? {
+            if line.is_empty() {
+                break;
+            }
+            view += &line;
+            view.push('\n');
+        }
+        Ok(Scaffold::parse(&view))
+    }
+
+    pub fn is_scaffold(&self, (x, y): Pos) -> bool {
+        (0..self.width as i64).contains(&x) && (0..self.height as i64).contains(&y) && self.tiles[y as usize * self.width + x as usize]
+    }
+
+    /// Scaffold with scaffold on all four sides, row by row.
+    pub fn intersections(&self) -> Vec<Pos> {
+        (0..self.height as i64)
+            .flat_map(|y| (0..self.width as i64).map(move |x| (x, y)))
+            .filter(|&pos| self.is_scaffold(pos) && Dir::ALL.iter().all(|d| self.is_scaffold(d.step(pos))))
+            .collect()
+    }
+
+    pub fn alignment_sum(&self) -> i64 {
+        self.intersections().iter().map(|(x, y)| x * y).sum()
+    }
+
+    /// Walks the robot to the end of the scaffold, going straight across
+    /// intersections and turning where it must. On a loop it stops when
+    /// back where it started. Empty without a robot on the scaffold.
+    pub fn trace(&self) -> Vec<Command> {
+        let Some((mut pos, mut dir)) = self.robot else {
+            return vec![];
+        };
+        let mut cmds = vec![];
+        let mut seen = HashSet::new();
+        loop {
+            if !seen.insert((pos, dir)) {
+                return cmds;
+            }
+            if self.is_scaffold(dir.step(pos)) {
+                pos = dir.step(pos);
+                match cmds.last_mut() {
+                    Some(Command::Move(n)) => *n += 1,
+                    _ => cmds.push(Command::Move(1)),
+                }
+            } else if self.is_scaffold(dir.turn_left().step(pos)) {
+                dir = dir.turn_left();
+                cmds.push(Command::Left);
+            } else if self.is_scaffold(dir.turn_right().step(pos)) {
+                dir = dir.turn_right();
+                cmds.push(Command::Right);
+            } else {
+                return cmds;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::{encode, Compressor};
+
+    #[test]
+    fn test_alignment() {
+        let scaffold = Scaffold::parse("\
+..#..........
+..#..........
+#######...###
+#.#...#...#.#
+#############
+..#...#...#..
+..#####...^..
+");
+        assert_eq!(scaffold.intersections(), [(2, 2), (2, 4), (6, 4), (10, 4)]);
+        assert_eq!(scaffold.alignment_sum(), 76);
+        assert_eq!(scaffold.robot, Some(((10, 6), Dir::North)));
+    }
+
+    #[test]
+    fn test_trace_and_compress() {
+        let scaffold = Scaffold::parse("\
+#######...#####
+#.....#...#...#
+#.....#...#...#
+......#...#...#
+......#...###.#
+......#.....#.#
+^########...#.#
+......#.#...#.#
+......#########
+........#...#..
+....#########..
+....#...#......
+....#...#......
+....#...#......
+....#####......
+");
+        let path = scaffold.trace();
+        assert_eq!(encode(&path), "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2");
+        let routines = Compressor::new(3, 20).shortest(&path).unwrap();
+        assert_eq!(routines.expand(), path);
+        assert!(routines.to_lines().lines().all(|l| l.len() <= 20));
+    }
+
+    #[test]
+    fn test_trace_ring() {
+        let scaffold = Scaffold::parse("\
+#####
+#...#
+^...#
+#####
+");
+        let path = scaffold.trace();
+        assert_eq!(encode(&path), "2,R,4,R,3,R,4,R,1");
+    }
+}
@@ -0,0 +1,200 @@
+use std::{collections::BTreeMap, fmt, ptr};
+
+use crate::mem::{Memory, PAGE_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub addr: usize,
+    pub old: i64,
+    pub new: i64,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} -> {}", self.addr, self.old, self.new)
+    }
+}
+
+/// Cells that differ between two memories, by address. Pages the two still
+/// share aren't looked at, so diffing a machine against its own snapshot
+/// costs what it wrote since.
+pub fn diff(old: &Memory, new: &Memory) -> Vec<Change> {
+    let old_pages: BTreeMap<usize, &[i64; PAGE_SIZE]> = old.page_iter().collect();
+    let new_pages: BTreeMap<usize, &[i64; PAGE_SIZE]> = new.page_iter().collect();
+    let mut ixs: Vec<usize> = old_pages.keys().chain(new_pages.keys()).copied().collect();
+    ixs.sort_unstable();
+    ixs.dedup();
+
+    let mut changes = vec![];
+    for ix in ixs {
+        let (a, b) = (old_pages.get(&ix), new_pages.get(&ix));
+        if let (Some(a), Some(b)) = (a, b) {
+            if ptr::eq(*a, *b) {
+                continue;
+            }
+        }
+        for offset in 0..PAGE_SIZE {
+            let (old, new) = (a.map_or(0, |p| p[offset]), b.map_or(0, |p| p[offset]));
+            if old != new {
+                changes.push(Change { addr: ix * PAGE_SIZE + offset, old, new });
+            }
+        }
+    }
+    changes
+}
+
+/// Addresses holding `val`, up to `mem.len()`.
+pub fn find(mem: &Memory, val: i64) -> Vec<usize> {
+    mem.iter().enumerate().filter(|&(_, v)| v == val).map(|(addr, _)| addr).collect()
+}
+
+/// How a candidate's value must relate to the one it had at the previous
+/// scan to stay a candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Equals(i64),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    ChangedBy(i64),
+}
+
+impl Filter {
+    pub fn matches(&self, old: i64, new: i64) -> bool {
+        match *self {
+            Filter::Equals(val) => new == val,
+            Filter::Changed => new != old,
+            Filter::Unchanged => new == old,
+            Filter::Increased => new > old,
+            Filter::Decreased => new < old,
+            Filter::ChangedBy(delta) => new.wrapping_sub(old) == delta,
+        }
+    }
+}
+
+/// Memory search narrowing down candidate addresses one observation at a
+/// time, the way cheat tools find where a game keeps its score: start with
+/// every cell, run the game, keep the cells that changed the way the score
+/// did, repeat.
+#[derive(Debug, Clone)]
+pub struct Scan {
+    /// Candidates and their values at the last scan.
+    candidates: BTreeMap<usize, i64>,
+}
+
+impl Scan {
+    /// Every address up to `mem.len()` is a candidate.
+    pub fn new(mem: &Memory) -> Scan {
+        Scan { candidates: mem.iter().enumerate().collect() }
+    }
+
+    /// Addresses currently holding `val`.
+    pub fn equal(mem: &Memory, val: i64) -> Scan {
+        let candidates = find(mem, val).into_iter().map(|addr| (addr, val)).collect();
+        Scan { candidates }
+    }
+
+    /// Keeps the candidates passing `filter` against `mem`, returns how many
+    /// are left.
+    pub fn keep(&mut self, mem: &Memory, filter: Filter) -> usize {
+        self.candidates.retain(|&addr, old| {
+            let new = mem.get(addr);
+            let keep = filter.matches(*old, new);
+            *old = new;
+            keep
+        });
+        self.candidates.len()
+    }
+
+    /// Keeps the candidates holding `observed`, a value seen from outside
+    /// like the score on screen.
+    pub fn track(&mut self, mem: &Memory, observed: i64) -> usize {
+        self.keep(mem, Filter::Equals(observed))
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Candidates with their values at the last scan.
+    pub fn candidates(&self) -> impl Iterator<Item = (usize, i64)> + '_ {
+        self.candidates.iter().map(|(&addr, &val)| (addr, val))
+    }
+
+    pub fn addrs(&self) -> Vec<usize> {
+        self.candidates.keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        int::{Computer, ComputerState},
+        test_util::aoc_program,
+    };
+
+    #[test]
+    fn test_diff() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+        let old = mem.clone();
+        mem[1] = 5;
+        mem.set(3000, 7).unwrap();
+        assert_eq!(diff(&old, &mem), [
+            Change { addr: 1, old: 2, new: 5 },
+            Change { addr: 3000, old: 0, new: 7 },
+        ]);
+        assert_eq!(diff(&mem, &old)[1], Change { addr: 3000, old: 7, new: 0 });
+        assert!(diff(&mem, &mem.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut mem = Memory::from(vec![5, 5, 5, 1]);
+        let mut scan = Scan::equal(&mem, 5);
+        assert_eq!(scan.addrs(), [0, 1, 2]);
+        mem[0] = 6;
+        mem[1] = 4;
+        assert_eq!(scan.keep(&mem, Filter::Changed), 2);
+        assert_eq!(scan.keep(&mem, Filter::Unchanged), 2);
+        mem[0] = 8;
+        mem[1] = 6;
+        assert_eq!(scan.keep(&mem, Filter::ChangedBy(2)), 2);
+        mem[0] = 7;
+        assert_eq!(scan.keep(&mem, Filter::Decreased), 1);
+        assert_eq!(scan.candidates().collect::<Vec<_>>(), [(0, 7)]);
+    }
+
+    fn run_frame(comp: &mut Computer, ball: &mut i64) {
+        while comp.run() == Ok(ComputerState::WriteInt) {}
+        for tile in Vec::from(std::mem::take(&mut comp.outs)).chunks(3) {
+            if tile[2] == 4 {
+                *ball = tile[0];
+            }
+        }
+        comp.ins.push_back(0);
+    }
+
+    // finds where the arcade keeps the ball by watching it on screen
+    #[test]
+    fn test_track_arcade_ball() {
+        let prog = aoc_program!(13);
+        let mut comp = Computer::new(&prog).with_patch(0, 2);
+        let mut scan = Scan::new(&comp.mem);
+        let mut ball = 0;
+        for _ in 0..20 {
+            run_frame(&mut comp, &mut ball);
+            scan.track(&comp.mem, ball);
+        }
+        assert_eq!(scan.addrs(), [388]);
+        for _ in 0..20 {
+            run_frame(&mut comp, &mut ball);
+            assert_eq!(comp.mem[388], ball);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{collections::VecDeque, fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{int::Computer, mem::{InvalidPages, Memory}};
+
+const MAGIC: &[u8; 4] = b"ICS1";
+
+/// Full machine state. Memory pages are shared with the machine the snapshot
+/// was taken from until either side writes to them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub eip: usize,
+    pub esp: usize,
+    pub mem: Memory,
+    pub ins: VecDeque<i64>,
+    pub outs: VecDeque<i64>,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    BadMagic,
+    Truncated,
+    Memory(InvalidPages),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::Json(e) => write!(f, "{}", e),
+            SnapshotError::BadMagic => write!(f, "not an Intcode snapshot"),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Memory(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+impl From<InvalidPages> for SnapshotError {
+    fn from(e: InvalidPages) -> Self {
+        SnapshotError::Memory(e)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(e: serde_json::Error) -> Self {
+        SnapshotError::Json(e)
+    }
+}
+
+impl Computer {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            eip: self.eip,
+            esp: self.esp,
+            mem: self.mem.clone(),
+            ins: self.ins.clone(),
+            outs: self.outs.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snap: &Snapshot) {
+        self.eip = snap.eip;
+        self.esp = snap.esp;
+        self.mem = snap.mem.clone();
+        self.ins = snap.ins.clone();
+        self.outs = snap.outs.clone();
+        if let Some(cache) = &mut self.blocks {
+            cache.revalidate(&self.mem);
+        }
+    }
+}
+
+impl From<&Snapshot> for Computer {
+    fn from(snap: &Snapshot) -> Self {
+        let mut comp = Computer::new(&Default::default());
+        comp.restore(snap);
+        comp
+    }
+}
+
+// LEB128 varint reader over a byte slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn u64(&mut self) -> Result<u64, SnapshotError> {
+        let mut val = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.bytes.split_first().ok_or(SnapshotError::Truncated)?;
+            self.bytes = rest;
+            val |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+        Err(SnapshotError::Truncated)
+    }
+
+    fn i64(&mut self) -> Result<i64, SnapshotError> {
+        let zz = self.u64()?;
+        Ok((zz >> 1) as i64 ^ -((zz & 1) as i64))
+    }
+
+    fn usize(&mut self) -> Result<usize, SnapshotError> {
+        usize::try_from(self.u64()?).map_err(|_| SnapshotError::Truncated)
+    }
+
+    fn words(&mut self) -> Result<Vec<i64>, SnapshotError> {
+        let n = self.usize()?;
+        // every word takes at least a byte
+        if n > self.bytes.len() {
+            return Err(SnapshotError::Truncated);
+        }
+        (0..n).map(|_| self.i64()).collect()
+    }
+}
+
+fn push_u64(out: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+// zigzag keeps small negative values short
+fn push_i64(out: &mut Vec<u8>, val: i64) {
+    push_u64(out, ((val << 1) ^ (val >> 63)) as u64);
+}
+
+fn push_words<'a>(out: &mut Vec<u8>, words: impl ExactSizeIterator<Item = &'a i64>) {
+    push_u64(out, words.len() as u64);
+    for &w in words {
+        push_i64(out, w);
+    }
+}
+
+impl Snapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Snapshot, SnapshotError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Compact binary form: magic, then varints for registers, memory
+    /// length and limit, each allocated page as its index and words up to
+    /// the last non-zero one, then the input and output queues. Word lists
+    /// are prefixed with their length, words are zigzag encoded.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        for v in [self.eip, self.esp, self.mem.len(), self.mem.limit(), self.mem.pages()] {
+            push_u64(&mut out, v as u64);
+        }
+        for (ix, page) in self.mem.page_iter() {
+            let used = page.iter().rposition(|&v| v != 0).map_or(0, |i| i + 1);
+            push_u64(&mut out, ix as u64);
+            push_words(&mut out, page[..used].iter());
+        }
+        push_words(&mut out, self.ins.iter());
+        push_words(&mut out, self.outs.iter());
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or(SnapshotError::BadMagic)?;
+        let mut r = Reader { bytes: rest };
+        let (eip, esp, len, limit, n_pages) = (r.usize()?, r.usize()?, r.usize()?, r.usize()?, r.usize()?);
+        let mut pages = vec![];
+        for _ in 0..n_pages {
+            let ix = r.usize()?;
+            let words = r.words()?;
+            pages.push((ix, Memory::page_from_words(ix, &words)?));
+        }
+        Ok(Snapshot {
+            eip,
+            esp,
+            mem: Memory::from_pages(len, limit, pages)?,
+            ins: r.words()?.into(),
+            outs: r.words()?.into(),
+        })
+    }
+
+    pub fn save_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    pub fn save_binary(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    /// Loads a snapshot saved in either format.
+    pub fn load(path: impl AsRef<Path>) -> Result<Snapshot, SnapshotError> {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(MAGIC) {
+            Snapshot::from_bytes(&bytes)
+        } else {
+            Ok(serde_json::from_slice(&bytes)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{int::ComputerState, mem::PAGE_SIZE, test_util::boost};
+
+    // BOOST in sensor boost mode, stopped somewhere in the middle of its run
+    fn paused_boost() -> Computer {
+        let mut comp = Computer::new(&boost()).with_input([2]);
+        for _ in 0..10_000 {
+            comp.step().unwrap();
+        }
+        comp.ins.push_back(7);
+        comp.outs.push_back(-3);
+        comp
+    }
+
+    fn finish(mut comp: Computer) -> Vec<i64> {
+        while comp.run() == Ok(ComputerState::WriteInt) {}
+        comp.outs.into()
+    }
+
+    fn assert_same(a: &Snapshot, b: &Snapshot) {
+        assert_eq!((a.eip, a.esp, &a.ins, &a.outs), (b.eip, b.esp, &b.ins, &b.outs));
+        assert_eq!((a.mem.len(), a.mem.limit()), (b.mem.len(), b.mem.limit()));
+        assert_eq!(a.mem.to_vec(), b.mem.to_vec());
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut comp = paused_boost();
+        let snap = comp.snapshot();
+        let expected = finish(comp.clone());
+        comp.run().unwrap();
+        comp.restore(&snap);
+        assert_eq!(finish(comp), expected);
+        assert_eq!(finish(Computer::from(&snap)), expected);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let snap = paused_boost().snapshot();
+        let back = Snapshot::from_json(&snap.to_json()).unwrap();
+        assert_same(&snap, &back);
+        assert!(Snapshot::from_json("{\"eip\": 1}").is_err());
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let mut comp = paused_boost();
+        comp.mem[100_000] = 5;
+        comp.outs.push_back(i64::MIN);
+        let snap = comp.snapshot();
+        let bytes = snap.to_bytes();
+        assert_same(&snap, &Snapshot::from_bytes(&bytes).unwrap());
+        assert!(bytes.len() < snap.to_json().len());
+        assert!(matches!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]), Err(SnapshotError::Truncated)));
+        assert!(matches!(Snapshot::from_bytes(b"nope"), Err(SnapshotError::BadMagic)));
+        // a page far past the length it claims
+        let mut bad = MAGIC.to_vec();
+        for v in [0, 0, 1, 100, 1, u64::MAX >> 1, 0, 0, 0] {
+            push_u64(&mut bad, v);
+        }
+        assert!(matches!(Snapshot::from_bytes(&bad), Err(SnapshotError::Memory(InvalidPages::PageOutOfRange { .. }))));
+        // a page with one word too many, then the same page twice
+        let mut long = MAGIC.to_vec();
+        for v in [0, 0, 1, 100, 1, 0, PAGE_SIZE as u64 + 1] {
+            push_u64(&mut long, v);
+        }
+        long.extend(std::iter::repeat_n(0, PAGE_SIZE + 3));
+        let err = SnapshotError::Memory(InvalidPages::PageTooLong { ix: 0, words: PAGE_SIZE + 1 });
+        assert_eq!(Snapshot::from_bytes(&long).unwrap_err().to_string(), err.to_string());
+        let mut dup = MAGIC.to_vec();
+        for v in [0, 0, 1, 100, 2, 0, 0, 0, 0, 0, 0] {
+            push_u64(&mut dup, v);
+        }
+        assert!(matches!(Snapshot::from_bytes(&dup), Err(SnapshotError::Memory(InvalidPages::DuplicatePage { ix: 0 }))));
+        let json = snap.to_json().replacen("\"limit\":16777216", "\"limit\":1", 1);
+        assert!(json.contains("\"limit\":1,"));
+        assert!(Snapshot::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn test_forks_share_pages() {
+        let comp = paused_boost();
+        let forks: Vec<Computer> = (0..1000).map(|_| Computer::from(&comp.snapshot())).collect();
+        assert!(forks.iter().all(|f| f.mem.unique_pages() == 0));
+        let mut fork = forks[0].clone();
+        fork.mem[0] = 1;
+        assert_eq!(fork.mem.unique_pages(), 1);
+        assert_eq!(comp.mem[0], forks[1].mem[0]);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{asm::assemble, int::Computer, program::Program};
+
+/// The puzzle program of an aoc19 day, `aoc_program!(9)` is BOOST.
+macro_rules! aoc_program {
+    ($day:literal) => {
+        include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/../../aoc19/aoc", $day, "/prog.txt"))
+            .parse::<$crate::program::Program>()
+            .unwrap()
+    };
+}
+
+pub(crate) use aoc_program;
+
+pub(crate) fn boost() -> Program {
+    aoc_program!(9)
+}
+
+pub(crate) fn assembled(src: &str) -> Computer {
+    Computer::new(&Program::from(assemble(src).unwrap().code))
+}
+
+/// Counts [counter] down from 3 and outputs each value.
+pub(crate) const COUNTDOWN: &str = "
+        add #3, #0, [counter]
+    loop: out [counter]
+        add #-1, [counter], [counter]
+        jt [counter], #loop
+        hlt
+    counter: .data 0
+";
+
+/// Counts down from its input, outputting every number.
+pub(crate) const COUNTDOWN_INPUT: &str = "
+        in [n]
+    loop: out [n]
+        add [n], #-1, [n]
+        jt [n], #loop
+        hlt
+    n: .data 0
+";
@@ -0,0 +1,315 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    io::{self, Write},
+};
+
+use serde::Serialize;
+
+use crate::int::{Computer, ComputerState, OpCode, Operation, VmError};
+
+/// One executed instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(into = "EventJson")]
+pub struct TraceEvent {
+    pub eip: usize,
+    pub esp: usize,
+    pub op: Operation,
+    /// Address and value the instruction stored.
+    pub write: Option<(usize, i64)>,
+    pub output: Option<i64>,
+}
+
+impl TraceEvent {
+    /// Params the opcode actually takes.
+    pub fn params(&self) -> &[i64] {
+        &self.op.params[..self.op.code.param_types().len()]
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+// serialized form, the opcode by mnemonic with only the params it takes
+#[derive(Serialize)]
+struct EventJson {
+    eip: usize,
+    esp: usize,
+    op: &'static str,
+    params: Vec<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    write: Option<(usize, i64)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<i64>,
+}
+
+impl From<TraceEvent> for EventJson {
+    fn from(event: TraceEvent) -> Self {
+        EventJson {
+            eip: event.eip,
+            esp: event.esp,
+            op: event.op.code.mnemonic(),
+            params: event.params().to_vec(),
+            write: event.write,
+            output: event.output,
+        }
+    }
+}
+
+pub trait Tracer {
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+impl<T: Tracer> Tracer for &mut T {
+    fn trace(&mut self, event: &TraceEvent) {
+        (**self).trace(event);
+    }
+}
+
+impl<T: Tracer> Tracer for Option<T> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if let Some(tracer) = self {
+            tracer.trace(event);
+        }
+    }
+}
+
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.0.trace(event);
+        self.1.trace(event);
+    }
+}
+
+/// Keeps the last `capacity` events.
+#[derive(Debug, Clone)]
+pub struct RingTracer {
+    pub events: VecDeque<TraceEvent>,
+    capacity: usize,
+}
+
+impl RingTracer {
+    pub fn new(capacity: usize) -> RingTracer {
+        RingTracer { events: VecDeque::with_capacity(capacity), capacity }
+    }
+}
+
+impl Tracer for RingTracer {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.events.len() == self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(*event);
+    }
+}
+
+/// Writes one JSON object per event. The first write error is kept in
+/// `error` and stops further writes.
+pub struct JsonLinesTracer<W: Write> {
+    out: W,
+    pub error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(out: W) -> JsonLinesTracer<W> {
+        JsonLinesTracer { out, error: None }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => self.out.flush().map(|_| self.out),
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.out, "{}", event.to_json()) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// Straight-line run of instructions ending at a jump or halt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Block {
+    pub start: usize,
+    /// Address of the last instruction.
+    pub end: usize,
+    /// Times the block was entered.
+    pub count: u64,
+    /// Instructions executed inside the block.
+    pub steps: u64,
+}
+
+/// Aggregated execution counts.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub steps: u64,
+    pub addrs: HashMap<usize, u64>,
+    pub opcodes: HashMap<OpCode, u64>,
+    blocks: HashMap<(usize, usize), Block>,
+    block: Option<(usize, u64)>,
+    next_eip: Option<usize>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile::default()
+    }
+
+    fn end_block(&mut self, end: usize) {
+        if let Some((start, steps)) = self.block.take() {
+            let block = self.blocks.entry((start, end))
+                .or_insert(Block { start, end, ..Block::default() });
+            block.count += 1;
+            block.steps += steps;
+        }
+    }
+
+    /// Most executed addresses, hottest first.
+    pub fn hottest_addrs(&self, n: usize) -> Vec<(usize, u64)> {
+        let mut addrs: Vec<(usize, u64)> = self.addrs.iter().map(|(&a, &c)| (a, c)).collect();
+        addrs.sort_by_key(|&(addr, count)| (std::cmp::Reverse(count), addr));
+        addrs.truncate(n);
+        addrs
+    }
+
+    /// Blocks that executed the most instructions, hottest first. A block
+    /// still running when the profile is read isn't counted yet.
+    pub fn hottest_blocks(&self, n: usize) -> Vec<Block> {
+        let mut blocks: Vec<Block> = self.blocks.values().copied().collect();
+        blocks.sort_by_key(|b| (std::cmp::Reverse(b.steps), b.start));
+        blocks.truncate(n);
+        blocks
+    }
+
+    pub fn opcode_histogram(&self) -> Vec<(OpCode, u64)> {
+        let mut hist: Vec<(OpCode, u64)> = OpCode::ALL.iter()
+            .filter_map(|code| self.opcodes.get(code).map(|&c| (*code, c)))
+            .collect();
+        hist.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        hist
+    }
+}
+
+impl Tracer for Profile {
+    fn trace(&mut self, event: &TraceEvent) {
+        // a resumed run may continue somewhere else than where it stopped
+        if self.next_eip.is_some_and(|eip| eip != event.eip) {
+            self.block = None;
+        }
+        self.steps += 1;
+        *self.addrs.entry(event.eip).or_default() += 1;
+        *self.opcodes.entry(event.op.code).or_default() += 1;
+        self.block.get_or_insert((event.eip, 0)).1 += 1;
+        match event.op.code {
+            OpCode::JumpIfTrue | OpCode::JumpIfFalse | OpCode::Halt => {
+                self.end_block(event.eip);
+                self.next_eip = None;
+            }
+            code => self.next_eip = Some(event.eip + code.size()),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions", self.steps)?;
+        writeln!(f, "opcodes:")?;
+        for (code, count) in self.opcode_histogram() {
+            writeln!(f, "  {:<4} {:>12}", code.mnemonic(), count)?;
+        }
+        writeln!(f, "hot blocks:")?;
+        for b in self.hottest_blocks(10) {
+            writeln!(f, "  {:>6}..{:<6} {:>12} steps {:>10} entries", b.start, b.end, b.steps, b.count)?;
+        }
+        writeln!(f, "hot addresses:")?;
+        for (addr, count) in self.hottest_addrs(10) {
+            writeln!(f, "  {:>6} {:>12}", addr, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Computer {
+    /// `step` that reports the executed instruction to `tracer`. Nothing is
+    /// reported while waiting for input.
+    pub fn step_traced(&mut self, tracer: &mut impl Tracer) -> Result<Option<ComputerState>, VmError> {
+        let (eip, esp) = (self.eip, self.esp);
+        let op = self.fetch()?;
+        let state = self.execute(op)?;
+        if state == Some(ComputerState::ReadInt) {
+            return Ok(state);
+        }
+        let write = op.write_addr().map(|addr| (addr as usize, self.mem.get(addr as usize)));
+        let output = match state {
+            Some(ComputerState::WriteInt) => self.outs.back().copied(),
+            _ => None,
+        };
+        tracer.trace(&TraceEvent { eip, esp, op, write, output });
+        Ok(state)
+    }
+
+    /// `run` that reports every executed instruction to `tracer`.
+    pub fn run_traced(&mut self, tracer: &mut impl Tracer) -> Result<ComputerState, VmError> {
+        loop {
+            if let Some(state) = self.step_traced(tracer)? {
+                return Ok(state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assembled, COUNTDOWN};
+
+    #[test]
+    fn test_ring_and_json() {
+        let mut comp = assembled("in [7]\nout [7]\nhlt\n.data 0");
+        let mut ring = RingTracer::new(2);
+        assert_eq!(comp.run_traced(&mut ring), Ok(ComputerState::ReadInt));
+        assert!(ring.events.is_empty());
+        comp.ins.push_back(5);
+        let mut json = JsonLinesTracer::new(vec![]);
+        let mut both = (ring, &mut json);
+        while comp.run_traced(&mut both) == Ok(ComputerState::WriteInt) {}
+        let (ring, _) = both;
+        assert_eq!(ring.events.len(), 2);
+        assert_eq!(ring.events[0].output, Some(5));
+        let mut none = RingTracer::new(0);
+        none.trace(&ring.events[0]);
+        assert!(none.events.is_empty());
+        assert_eq!(String::from_utf8(json.finish().unwrap()).unwrap(), "\
+{\"eip\":0,\"esp\":0,\"op\":\"in\",\"params\":[7],\"write\":[7,5]}
+{\"eip\":2,\"esp\":0,\"op\":\"out\",\"params\":[5],\"output\":5}
+{\"eip\":4,\"esp\":0,\"op\":\"hlt\",\"params\":[]}
+");
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut comp = assembled(COUNTDOWN);
+        let mut profile = Profile::new();
+        while comp.run_traced(&mut profile) == Ok(ComputerState::WriteInt) {}
+        assert_eq!(profile.steps, 11);
+        assert_eq!(profile.addrs[&4], 3);
+        assert_eq!(profile.opcodes[&OpCode::Add], 4);
+        assert_eq!(profile.opcode_histogram()[0], (OpCode::Add, 4));
+        // outputs stop the run mid-block without splitting it
+        assert_eq!(profile.hottest_blocks(3), [
+            Block { start: 4, end: 10, count: 2, steps: 6 },
+            Block { start: 0, end: 10, count: 1, steps: 4 },
+            Block { start: 13, end: 13, count: 1, steps: 1 },
+        ]);
+    }
+}